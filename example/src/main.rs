@@ -1,5 +1,5 @@
 use log::{debug, info};
-use lsplt_rs::MapInfo;
+use lsplt_rs::LoadedModule;
 
 #[no_mangle]
 extern "C" fn get_pid() -> i32 {
@@ -11,38 +11,17 @@ fn main() {
     init_logger();
     info!("Logger initialized");
 
-    let map_info = MapInfo::scan("self");
-    let prog_name = std::env::current_exe()
-        .unwrap()
-        .file_name()
-        .unwrap()
-        .to_str()
-        .unwrap()
-        .to_string();
-
     info!("Current PID: {}", unsafe { libc::getpid() });
 
-    let self_info = &map_info
-        .iter()
-        .find(|mi| {
-            if let Some(path) = &mi.pathname {
-                if mi.perms & (libc::PROT_EXEC as u8) == 0 && path.ends_with(&prog_name) {
-                    return true;
-                }
-            }
-            false
-        })
-        .expect("libc not found in memory maps");
-    info!("libc info: {:?}", self_info);
-
-    let mut original_ptr: *mut std::ffi::c_void = std::ptr::null_mut();
+    let self_info = LoadedModule::find_by_address(get_pid as *const std::ffi::c_void)
+        .expect("executable not found in memory maps");
+    info!("executable info: {:?}", self_info);
 
-    lsplt_rs::register_hook(
-        self_info.dev,
-        self_info.inode,
+    let hook = lsplt_rs::Hook::new(
+        self_info.dev(),
+        self_info.inode(),
         "getpid",
-        get_pid as *mut std::ffi::c_void,
-        Some(&mut original_ptr),
+        get_pid as extern "C" fn() -> i32,
     )
     .unwrap();
 
@@ -51,14 +30,10 @@ fn main() {
     debug!("hook committed");
 
     info!("Current PID: {}", unsafe { libc::getpid() });
-    info!("Original PID: {}", unsafe {
-        if original_ptr.is_null() {
-            panic!("Original function pointer is null\nWhich means the hook registration failed.");
-        } else {
-            let original_fn: extern "C" fn() -> i32 = std::mem::transmute(original_ptr);
-            original_fn()
-        }
-    });
+    let original_fn = hook
+        .original()
+        .expect("Original function pointer is null\nWhich means the hook registration failed.");
+    info!("Original PID: {}", original_fn());
 }
 
 pub fn init_logger() {
@@ -0,0 +1,182 @@
+//! Procedural macros for `lsplt-rs`.
+//!
+//! Use them through the `macros` feature of `lsplt-rs`, which re-exports [`plt_hook`].
+
+use proc_macro::TokenStream;
+use quote::quote;
+use syn::parse::Parser;
+use syn::spanned::Spanned;
+use syn::{FnArg, ItemFn, LitStr, ReturnType};
+
+/// Turns a function into a PLT hook.
+///
+/// The function is made `extern "C"`, and a type with the same name is generated next to it
+/// with the following associated functions:
+/// - `install() -> lsplt_rs::Result<()>` registers the function as the hook of `symbol` in the
+///   loaded library matching `library` with `lsplt_rs::register_hook_by_name`, and commits.
+/// - `uninstall() -> lsplt_rs::Result<()>` restores the original function and commits.
+///   `original()` returns `None` again afterwards.
+/// - `original() -> Option<extern "C" fn(..) -> ..>` returns the original function while the
+///   hook is installed.
+///
+/// Since no module is generated, the function can also be declared inside a function body.
+///
+/// # Arguments
+/// * `library` - The file name pattern of the library to hook, e.g. `"libc.so"`. `*` and `?` can
+///   be used as wildcards.
+/// * `symbol` - The symbol to hook. Defaults to the name of the function.
+///
+/// # Examples
+/// The example is part of the documentation of the re-export in `lsplt-rs`, where it is compiled
+/// as a doctest.
+#[proc_macro_attribute]
+pub fn plt_hook(args: TokenStream, item: TokenStream) -> TokenStream {
+    match expand(args.into(), item.into()) {
+        Ok(tokens) => tokens.into(),
+        Err(err) => err.to_compile_error().into(),
+    }
+}
+
+fn expand(
+    args: proc_macro2::TokenStream,
+    item: proc_macro2::TokenStream,
+) -> syn::Result<proc_macro2::TokenStream> {
+    let mut library: Option<LitStr> = None;
+    let mut symbol: Option<LitStr> = None;
+    let parser = syn::meta::parser(|meta| {
+        if meta.path.is_ident("library") {
+            library = Some(meta.value()?.parse()?);
+            Ok(())
+        } else if meta.path.is_ident("symbol") {
+            symbol = Some(meta.value()?.parse()?);
+            Ok(())
+        } else {
+            Err(meta.error("expected `library` or `symbol`"))
+        }
+    });
+    parser.parse2(args)?;
+
+    let mut function: ItemFn = syn::parse2(item)?;
+    let library = library.ok_or_else(|| {
+        syn::Error::new(
+            function.sig.ident.span(),
+            "missing `library = \"...\"` argument",
+        )
+    })?;
+    let symbol = symbol
+        .unwrap_or_else(|| LitStr::new(&function.sig.ident.to_string(), function.sig.ident.span()));
+
+    let sig = &mut function.sig;
+    if let Some(abi) = &sig.abi {
+        if abi.name.as_ref().is_some_and(|name| name.value() != "C") {
+            return Err(syn::Error::new(abi.span(), "PLT hooks must use the C ABI"));
+        }
+    }
+    sig.abi = Some(syn::parse_quote!(extern "C"));
+    if let Some(asyncness) = &sig.asyncness {
+        return Err(syn::Error::new(
+            asyncness.span(),
+            "PLT hooks cannot be async",
+        ));
+    }
+    if !sig.generics.params.is_empty() {
+        return Err(syn::Error::new(
+            sig.generics.span(),
+            "PLT hooks cannot be generic",
+        ));
+    }
+    if let Some(variadic) = &sig.variadic {
+        return Err(syn::Error::new(
+            variadic.span(),
+            "PLT hooks cannot be variadic",
+        ));
+    }
+
+    let mut arg_types = Vec::new();
+    for input in &sig.inputs {
+        match input {
+            FnArg::Typed(arg) => arg_types.push(&arg.ty),
+            FnArg::Receiver(receiver) => {
+                return Err(syn::Error::new(
+                    receiver.span(),
+                    "PLT hooks cannot take `self`",
+                ));
+            }
+        }
+    }
+    let output = match &sig.output {
+        ReturnType::Default => quote!(),
+        ReturnType::Type(arrow, ty) => quote!(#arrow #ty),
+    };
+    let unsafety = &sig.unsafety;
+    let fn_type = quote!(#unsafety extern "C" fn(#(#arg_types),*) #output);
+
+    let name = sig.ident.clone();
+    let vis = function.vis.clone();
+    let doc = format!(
+        "Installs, uninstalls and calls through the `{symbol}` hook [`{name}()`].",
+        symbol = symbol.value()
+    );
+
+    Ok(quote! {
+        #function
+
+        #[doc = #doc]
+        #[allow(non_camel_case_types)]
+        #vis struct #name {}
+
+        impl #name {
+            /// The storage of the original function, null while the hook is not installed.
+            fn slot() -> &'static ::std::sync::atomic::AtomicPtr<::std::ffi::c_void> {
+                static ORIGINAL: ::std::sync::atomic::AtomicPtr<::std::ffi::c_void> =
+                    ::std::sync::atomic::AtomicPtr::new(::std::ptr::null_mut());
+                &ORIGINAL
+            }
+
+            /// The original function, or `None` if the hook is not installed.
+            pub fn original() -> ::std::option::Option<#fn_type> {
+                let original = Self::slot().load(::std::sync::atomic::Ordering::Acquire);
+                if original.is_null() {
+                    return ::std::option::Option::None;
+                }
+                // SAFETY: the original was read from the GOT slot of the hooked symbol, which has
+                // the signature of the hook.
+                ::std::option::Option::Some(unsafe {
+                    ::std::mem::transmute::<*mut ::std::ffi::c_void, #fn_type>(original)
+                })
+            }
+
+            /// Registers the hook in the matching library and commits.
+            pub fn install() -> ::lsplt_rs::Result<()> {
+                ::lsplt_rs::register_hook_by_name(
+                    #library,
+                    #symbol,
+                    #name as *mut ::std::ffi::c_void,
+                    // SAFETY: the backend writes the original to this static during commit.
+                    ::std::option::Option::Some(unsafe { &mut *Self::slot().as_ptr() }),
+                )?;
+                ::lsplt_rs::commit_hook()
+            }
+
+            /// Restores the original function and commits.
+            pub fn uninstall() -> ::lsplt_rs::Result<()> {
+                let original = Self::slot().load(::std::sync::atomic::Ordering::Acquire);
+                if original.is_null() {
+                    return ::std::result::Result::Ok(());
+                }
+                ::lsplt_rs::register_hook_by_name(
+                    #library,
+                    #symbol,
+                    original,
+                    ::std::option::Option::None,
+                )?;
+                ::lsplt_rs::commit_hook()?;
+                Self::slot().store(
+                    ::std::ptr::null_mut(),
+                    ::std::sync::atomic::Ordering::Release,
+                );
+                ::std::result::Result::Ok(())
+            }
+        }
+    })
+}
@@ -19,17 +19,25 @@ fn main() {
     let manifest_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
     let jni_dir = manifest_dir.join("LSPlt/lsplt/src/main/jni");
     emit_rerun_for_dir(&jni_dir);
+    emit_rerun_for_dir(&manifest_dir.join("host"));
 
     let out_dir = PathBuf::from(env::var("OUT_DIR").expect("OUT_DIR is not set"));
     let target_os = env::var("CARGO_CFG_TARGET_OS").expect("CARGO_CFG_TARGET_OS is not set");
 
     sanitize_clang_path_env();
 
-    if env::var("DOCS_RS").is_ok() || target_os != "android" {
+    if env::var("DOCS_RS").is_ok() || (target_os != "android" && target_os != "linux") {
         generate_bindings(&manifest_dir, &out_dir, None, None);
         return;
     }
 
+    if target_os == "linux" {
+        generate_bindings(&manifest_dir, &out_dir, None, None);
+        ensure_lsplt_sources(&jni_dir);
+        build_lsplt_host(&manifest_dir, &jni_dir);
+        return;
+    }
+
     let target_arch = env::var("CARGO_CFG_TARGET_ARCH").expect("CARGO_CFG_TARGET_ARCH is not set");
     let target = env::var("TARGET").expect("TARGET is not set");
     let android_platform = resolve_android_platform();
@@ -49,12 +57,7 @@ fn main() {
 
     generate_bindings(&manifest_dir, &out_dir, Some(&sysroot), Some(&clang_target));
 
-    if !jni_dir.exists() {
-        run(
-            Command::new("git").args(["submodule", "update", "--init", "--recursive"]),
-            "init LSPlt submodule",
-        );
-    }
+    ensure_lsplt_sources(&jni_dir);
 
     build_lsplt(&manifest_dir, &jni_dir, &ndk, &prebuilt_tag, &clang_target, &sysroot);
 
@@ -115,6 +118,43 @@ fn build_lsplt(
     build.compile("lsplt_static");
 }
 
+/// Builds the LSPlt core for a host (non-Android) Linux target with the system C++ toolchain.
+///
+/// The compiler is picked by `cc` (`CXX`/`CXX_<target>`, falling back to `c++`), and the C++
+/// standard library is linked through `cc` as well, so `CXXSTDLIB` can be used to switch
+/// between `stdc++` and `c++` (e.g. for musl toolchains shipping libc++).
+fn build_lsplt_host(manifest_dir: &Path, jni_dir: &Path) {
+    let host_dir = manifest_dir.join("host");
+
+    let mut build = cc::Build::new();
+    build.cpp(true);
+    build.std("c++20");
+    // Provides <android/log.h> and the Android-only ELF constants missing from glibc/musl.
+    build.include(&host_dir);
+    build.flag("-include");
+    build.flag(
+        host_dir
+            .join("lsplt_host_compat.h")
+            .to_str()
+            .expect("Non-UTF8 host compat header path"),
+    );
+    build.include(jni_dir);
+    build.include(jni_dir.join("include"));
+    build.file(manifest_dir.join("wrapper.cc"));
+    build.file(jni_dir.join("lsplt.cc"));
+    build.file(jni_dir.join("elf_util.cc"));
+    build.compile("lsplt_static");
+}
+
+fn ensure_lsplt_sources(jni_dir: &Path) {
+    if !jni_dir.exists() {
+        run(
+            Command::new("git").args(["submodule", "update", "--init", "--recursive"]),
+            "init LSPlt submodule",
+        );
+    }
+}
+
 fn generate_bindings(
     manifest_dir: &Path,
     out_dir: &Path,
@@ -133,6 +173,7 @@ fn generate_bindings(
         .allowlist_type("lsplt.*")
         .allowlist_function("lsplt.*")
         .allowlist_var("lsplt.*")
+        .allowlist_var("LSPLT_.*")
         .opaque_type("std::.*")
         .clang_arg(format!("-I{}", include_dir.to_str().expect("Non-UTF8 include path")))
         .clang_arg("-std=c++20")
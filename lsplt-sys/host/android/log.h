@@ -0,0 +1,37 @@
+// Minimal <android/log.h> replacement used when building LSPlt for host Linux.
+// Log lines are written to stderr instead of logcat.
+#pragma once
+
+#include <stdarg.h>
+#include <stdio.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+typedef enum android_LogPriority {
+    ANDROID_LOG_UNKNOWN = 0,
+    ANDROID_LOG_DEFAULT,
+    ANDROID_LOG_VERBOSE,
+    ANDROID_LOG_DEBUG,
+    ANDROID_LOG_INFO,
+    ANDROID_LOG_WARN,
+    ANDROID_LOG_ERROR,
+    ANDROID_LOG_FATAL,
+    ANDROID_LOG_SILENT,
+} android_LogPriority;
+
+static inline int __android_log_print(int prio, const char* tag, const char* fmt, ...) {
+    static const char kLevels[] = "??VDIWEFS";
+    va_list args;
+    va_start(args, fmt);
+    int written = fprintf(stderr, "%c/%s: ", kLevels[prio & 0x7], tag ? tag : "");
+    written += vfprintf(stderr, fmt, args);
+    written += fprintf(stderr, "\n");
+    va_end(args);
+    return written;
+}
+
+#ifdef __cplusplus
+}
+#endif
@@ -0,0 +1,23 @@
+// Force-included when building LSPlt for host Linux (glibc/musl).
+// Bionic's <elf.h> defines the Android packed relocation tags; other libcs do not.
+#pragma once
+
+#include <elf.h>
+
+#ifndef DT_ANDROID_REL
+#define DT_ANDROID_REL 0x6000000f
+#endif
+#ifndef DT_ANDROID_RELSZ
+#define DT_ANDROID_RELSZ 0x60000010
+#endif
+#ifndef DT_ANDROID_RELA
+#define DT_ANDROID_RELA 0x60000011
+#endif
+#ifndef DT_ANDROID_RELASZ
+#define DT_ANDROID_RELASZ 0x60000012
+#endif
+
+// clang and GCC >= 12 provide this; keep older GCC releases working.
+#ifndef __FILE_NAME__
+#define __FILE_NAME__ __FILE__
+#endif
@@ -5,18 +5,36 @@
 #include <vector>
 #include <string>
 #include <cstring>
+#include <new>
+
+namespace {
+
+thread_local int last_error = LSPLT_ERROR_NONE;
+
+bool set_result(bool result) {
+    last_error = result ? LSPLT_ERROR_NONE : LSPLT_ERROR_FAILED;
+    return result;
+}
+
+} // namespace
 
 extern "C" {
 
+int lsplt_last_error(void) {
+    return last_error;
+}
+
 lsplt_map_info_array_t lsplt_scan(const char* pid) {
     lsplt_map_info_array_t result = {nullptr, 0};
     
     try {
         std::string pid_str = pid ? pid : "self";
         auto cpp_result = lsplt::v2::MapInfo::Scan(pid_str);
+        last_error = LSPLT_ERROR_NONE;
         
+        // Only set the size once the array exists, so a failed allocation yields an empty result.
+        result.data = new lsplt_map_info_t[cpp_result.size()]();
         result.size = cpp_result.size();
-        result.data = new lsplt_map_info_t[result.size];
         
         for (size_t i = 0; i < result.size; ++i) {
             const auto& src = cpp_result[i];
@@ -38,10 +56,12 @@ lsplt_map_info_array_t lsplt_scan(const char* pid) {
                 dest.path = nullptr;
             }
         }
+    } catch (const std::bad_alloc&) {
+        lsplt_free_map_info_array(&result);
+        last_error = LSPLT_ERROR_OUT_OF_MEMORY;
     } catch (...) {
-        // Handle exceptions
-        result.data = nullptr;
-        result.size = 0;
+        lsplt_free_map_info_array(&result);
+        last_error = LSPLT_ERROR_EXCEPTION;
     }
     
     return result;
@@ -62,12 +82,24 @@ void lsplt_free_map_info_array(lsplt_map_info_array_t* array) {
 
 bool lsplt_register_hook(dev_t dev, ino_t inode, const char* symbol, 
                         void* callback, void** backup) {
-    if (!symbol) return false;
+    if (!symbol) {
+        last_error = LSPLT_ERROR_INVALID_ARGUMENT;
+        return false;
+    }
     
     try {
-        return lsplt::v2::RegisterHook(dev, inode, std::string_view(symbol), 
-                                      callback, backup);
+        if (lsplt::v2::RegisterHook(dev, inode, std::string_view(symbol), 
+                                    callback, backup)) {
+            last_error = LSPLT_ERROR_NONE;
+            return true;
+        }
+        last_error = LSPLT_ERROR_INVALID_ARGUMENT;
+        return false;
+    } catch (const std::bad_alloc&) {
+        last_error = LSPLT_ERROR_OUT_OF_MEMORY;
+        return false;
     } catch (...) {
+        last_error = LSPLT_ERROR_EXCEPTION;
         return false;
     }
 }
@@ -75,28 +107,48 @@ bool lsplt_register_hook(dev_t dev, ino_t inode, const char* symbol,
 bool lsplt_register_hook_with_offset(dev_t dev, ino_t inode, uintptr_t offset,
                                    size_t size, const char* symbol,
                                    void* callback, void** backup) {
-    if (!symbol) return false;
+    if (!symbol) {
+        last_error = LSPLT_ERROR_INVALID_ARGUMENT;
+        return false;
+    }
     
     try {
-        return lsplt::v2::RegisterHook(dev, inode, offset, size, 
-                                      std::string_view(symbol), callback, backup);
+        if (lsplt::v2::RegisterHook(dev, inode, offset, size, 
+                                    std::string_view(symbol), callback, backup)) {
+            last_error = LSPLT_ERROR_NONE;
+            return true;
+        }
+        last_error = LSPLT_ERROR_INVALID_ARGUMENT;
+        return false;
+    } catch (const std::bad_alloc&) {
+        last_error = LSPLT_ERROR_OUT_OF_MEMORY;
+        return false;
     } catch (...) {
+        last_error = LSPLT_ERROR_EXCEPTION;
         return false;
     }
 }
 
 bool lsplt_commit_hook(void) {
     try {
-        return lsplt::v2::CommitHook();
+        return set_result(lsplt::v2::CommitHook());
+    } catch (const std::bad_alloc&) {
+        last_error = LSPLT_ERROR_OUT_OF_MEMORY;
+        return false;
     } catch (...) {
+        last_error = LSPLT_ERROR_EXCEPTION;
         return false;
     }
 }
 
 bool lsplt_invalidate_backup(void) {
     try {
-        return lsplt::v2::InvalidateBackup();
+        return set_result(lsplt::v2::InvalidateBackup());
+    } catch (const std::bad_alloc&) {
+        last_error = LSPLT_ERROR_OUT_OF_MEMORY;
+        return false;
     } catch (...) {
+        last_error = LSPLT_ERROR_EXCEPTION;
         return false;
     }
 }
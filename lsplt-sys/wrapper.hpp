@@ -7,6 +7,17 @@
 extern "C" {
 #endif
 
+// Error codes reported by lsplt_last_error()
+#define LSPLT_ERROR_NONE 0
+// An argument was rejected by the wrapper or by LSPlt
+#define LSPLT_ERROR_INVALID_ARGUMENT 1
+// LSPlt returned false; errno may hold the cause
+#define LSPLT_ERROR_FAILED 2
+// LSPlt threw a C++ exception
+#define LSPLT_ERROR_EXCEPTION 3
+// LSPlt ran out of memory (std::bad_alloc)
+#define LSPLT_ERROR_OUT_OF_MEMORY 4
+
 // C wrapper for lsplt::v2::MapInfo
 typedef struct {
     uintptr_t start;
@@ -25,6 +36,13 @@ typedef struct {
     size_t size;
 } lsplt_map_info_array_t;
 
+/**
+ * @brief Returns the error code of the last wrapper call made on this thread
+ *
+ * @return One of the LSPLT_ERROR_* codes, LSPLT_ERROR_NONE if the call succeeded
+ */
+int lsplt_last_error(void);
+
 /**
  * @brief Scans /proc/pid/maps and returns memory mapping information
  * 
@@ -0,0 +1,428 @@
+//! Locating libraries stored inside zip archives such as APKs.
+//!
+//! Android can map a library directly from an APK if it is stored uncompressed at a page-aligned
+//! offset. Only the zip central directory and the local file headers are read here.
+
+use std::fs::File;
+use std::os::unix::fs::{FileExt, MetadataExt};
+use std::path::Path;
+
+use crate::elf::ELF_MAGIC;
+use crate::maps::page_size;
+use crate::{DeviceId, Error, Inode, Result};
+
+const EOCD_SIGNATURE: u32 = 0x0605_4b50;
+const EOCD_SIZE: u64 = 22;
+const ZIP64_EOCD_LOCATOR_SIGNATURE: u32 = 0x0706_4b50;
+const ZIP64_EOCD_LOCATOR_SIZE: u64 = 20;
+const ZIP64_EOCD_SIGNATURE: u32 = 0x0606_4b50;
+const CENTRAL_HEADER_SIGNATURE: u32 = 0x0201_4b50;
+const CENTRAL_HEADER_SIZE: usize = 46;
+const LOCAL_HEADER_SIGNATURE: u32 = 0x0403_4b50;
+const LOCAL_HEADER_SIZE: usize = 30;
+const ZIP64_EXTRA_ID: u16 = 0x0001;
+pub(crate) const METHOD_STORED: u16 = 0;
+
+/// A library stored inside an archive, with the arguments to pass to
+/// [`register_hook_with_offset`](crate::register_hook_with_offset).
+///
+/// # Examples
+/// ```no_run
+/// # extern "C" fn my_getpid() -> i32 { 2333 }
+/// use lsplt_rs::ArchiveEntry;
+///
+/// let entry = ArchiveEntry::find("/data/app/base.apk", "lib/arm64-v8a/libfoo.so")?;
+/// lsplt_rs::register_hook_with_offset(
+///     entry.dev,
+///     entry.inode,
+///     entry.offset,
+///     entry.size,
+///     "getpid",
+///     my_getpid as *mut _,
+///     None,
+/// )?;
+/// # Ok::<(), lsplt_rs::Error>(())
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ArchiveEntry {
+    /// The device number of the archive.
+    pub dev: DeviceId,
+    /// The inode of the archive.
+    pub inode: Inode,
+    /// The offset of the entry data in the archive.
+    pub offset: usize,
+    /// The size of the entry data.
+    pub size: usize,
+}
+
+impl ArchiveEntry {
+    /// Finds the entry `name` in the zip archive at `archive`.
+    ///
+    /// # Arguments
+    /// * `archive` - The path to the archive, e.g. an APK.
+    /// * `name` - The full name of the entry, e.g. `lib/arm64-v8a/libfoo.so`.
+    ///
+    /// # Returns
+    /// The location of the entry, or an [`Error`] on failure, e.g.
+    /// [`Error::ArchiveEntryNotFound`] if the archive has no such entry, or
+    /// [`Error::UnsupportedArchiveEntry`] if it is compressed or not page aligned and thus cannot
+    /// be mapped directly.
+    pub fn find(archive: impl AsRef<Path>, name: &str) -> Result<Self> {
+        let file = File::open(archive)?;
+        let metadata = file.metadata()?;
+        let entry = central_directory(&file)?
+            .into_iter()
+            .find(|entry| entry.name == name)
+            .ok_or_else(|| Error::ArchiveEntryNotFound {
+                name: name.to_owned(),
+            })?;
+        let unsupported = |reason| Error::UnsupportedArchiveEntry {
+            name: name.to_owned(),
+            reason,
+        };
+        if entry.method != METHOD_STORED {
+            return Err(unsupported("the entry is compressed"));
+        }
+        let offset = entry.data_offset(&file)?;
+        if offset % page_size() as u64 != 0 {
+            return Err(unsupported("the entry is not page aligned"));
+        }
+        Ok(ArchiveEntry {
+            dev: metadata.dev() as DeviceId,
+            inode: metadata.ino() as Inode,
+            offset: offset as usize,
+            size: entry.size as usize,
+        })
+    }
+}
+
+/// A library stored inside an archive, as returned by
+/// [`MapInfo::embedded_library`](crate::MapInfo::embedded_library).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EmbeddedLibrary {
+    /// The name of the archive entry, e.g. `lib/arm64-v8a/libfoo.so`.
+    pub name: String,
+    /// The offset of the ELF header in the archive, which is the `offset` to pass to
+    /// [`register_hook_with_offset`](crate::register_hook_with_offset).
+    pub offset: usize,
+    /// The size of the library.
+    pub size: usize,
+}
+
+/// Finds the library stored in the archive at `path` that contains the file offset `offset`.
+///
+/// Returns `None` if the file is not a zip archive, is not the file identified by `dev` and
+/// `inode`, or has no uncompressed ELF entry at `offset`.
+pub(crate) fn find_embedded_library(
+    path: &str,
+    dev: DeviceId,
+    inode: Inode,
+    offset: usize,
+) -> Result<Option<EmbeddedLibrary>> {
+    let file = File::open(path)?;
+    let metadata = file.metadata()?;
+    if metadata.dev() as DeviceId != dev || metadata.ino() as Inode != inode {
+        return Ok(None);
+    }
+    let mut magic = [0; 4];
+    if file.read_exact_at(&mut magic, 0).is_err() || u32_at(&magic, 0) != LOCAL_HEADER_SIGNATURE {
+        return Ok(None);
+    }
+
+    let offset = offset as u64;
+    let Some(entry) = central_directory(&file)?
+        .into_iter()
+        .filter(|entry| entry.local_header_offset <= offset)
+        .max_by_key(|entry| entry.local_header_offset)
+    else {
+        return Ok(None);
+    };
+    let start = entry.data_offset(&file)?;
+    if entry.method != METHOD_STORED || !(start..start.saturating_add(entry.size)).contains(&offset)
+    {
+        return Ok(None);
+    }
+    if file.read_exact_at(&mut magic, start).is_err() || magic != ELF_MAGIC {
+        return Ok(None);
+    }
+    Ok(Some(EmbeddedLibrary {
+        name: entry.name,
+        offset: start as usize,
+        size: entry.size as usize,
+    }))
+}
+
+/// An entry of the central directory of a zip archive.
+#[derive(Debug, Clone)]
+pub(crate) struct CentralEntry {
+    pub(crate) name: String,
+    pub(crate) method: u16,
+    /// The uncompressed size.
+    pub(crate) size: u64,
+    pub(crate) local_header_offset: u64,
+}
+
+impl CentralEntry {
+    /// Reads the local file header of the entry to find where its data starts.
+    pub(crate) fn data_offset(&self, file: &File) -> Result<u64> {
+        let mut header = [0; LOCAL_HEADER_SIZE];
+        file.read_exact_at(&mut header, self.local_header_offset)?;
+        if u32_at(&header, 0) != LOCAL_HEADER_SIGNATURE {
+            return Err(Error::InvalidArchive("bad local file header"));
+        }
+        let name_len = u16_at(&header, 26) as u64;
+        let extra_len = u16_at(&header, 28) as u64;
+        Ok(self.local_header_offset + LOCAL_HEADER_SIZE as u64 + name_len + extra_len)
+    }
+}
+
+/// Reads the central directory of the zip archive `file`.
+pub(crate) fn central_directory(file: &File) -> Result<Vec<CentralEntry>> {
+    let (count, size, offset) = find_directory(file)?;
+    if offset.saturating_add(size) > file.metadata()?.len() {
+        return Err(Error::InvalidArchive("central directory out of bounds"));
+    }
+    let mut directory = vec![0; size as usize];
+    file.read_exact_at(&mut directory, offset)?;
+
+    let mut entries = Vec::with_capacity(count.min(size / CENTRAL_HEADER_SIZE as u64) as usize);
+    let mut rest = &directory[..];
+    for _ in 0..count {
+        if rest.len() < CENTRAL_HEADER_SIZE || u32_at(rest, 0) != CENTRAL_HEADER_SIGNATURE {
+            return Err(Error::InvalidArchive("bad central directory entry"));
+        }
+        let name_len = u16_at(rest, 28) as usize;
+        let extra_len = u16_at(rest, 30) as usize;
+        let comment_len = u16_at(rest, 32) as usize;
+        let end = CENTRAL_HEADER_SIZE + name_len + extra_len + comment_len;
+        if rest.len() < end {
+            return Err(Error::InvalidArchive("bad central directory entry"));
+        }
+        let name = &rest[CENTRAL_HEADER_SIZE..CENTRAL_HEADER_SIZE + name_len];
+        let extra =
+            &rest[CENTRAL_HEADER_SIZE + name_len..CENTRAL_HEADER_SIZE + name_len + extra_len];
+
+        let mut size = u32_at(rest, 24) as u64;
+        let mut compressed_size = u32_at(rest, 20) as u64;
+        let mut local_header_offset = u32_at(rest, 42) as u64;
+        // ZIP64 values replace the saturated 32-bit fields, in this order.
+        let mut zip64 = zip64_extra(extra)
+            .chunks_exact(8)
+            .map(|value| u64_at(value, 0));
+        for field in [&mut size, &mut compressed_size, &mut local_header_offset] {
+            if *field == u32::MAX as u64 {
+                *field = zip64
+                    .next()
+                    .ok_or(Error::InvalidArchive("missing ZIP64 extra field"))?;
+            }
+        }
+
+        entries.push(CentralEntry {
+            name: String::from_utf8_lossy(name).into_owned(),
+            method: u16_at(rest, 10),
+            size,
+            local_header_offset,
+        });
+        rest = &rest[end..];
+    }
+    Ok(entries)
+}
+
+/// Finds `(entry count, size, offset)` of the central directory from the end of central
+/// directory record.
+fn find_directory(file: &File) -> Result<(u64, u64, u64)> {
+    let len = file.metadata()?.len();
+    if len < EOCD_SIZE {
+        return Err(Error::InvalidArchive("too short for a zip archive"));
+    }
+    // The record is followed by a comment of at most 64 KiB.
+    let tail_len = len.min(EOCD_SIZE + u16::MAX as u64);
+    let mut tail = vec![0; tail_len as usize];
+    file.read_exact_at(&mut tail, len - tail_len)?;
+    let eocd = (0..=tail.len() - EOCD_SIZE as usize)
+        .rev()
+        .find(|&pos| u32_at(&tail, pos) == EOCD_SIGNATURE)
+        .ok_or(Error::InvalidArchive("end of central directory not found"))?;
+    let eocd_offset = len - tail_len + eocd as u64;
+    let record = &tail[eocd..];
+
+    let count = u16_at(record, 10) as u64;
+    let size = u32_at(record, 12) as u64;
+    let offset = u32_at(record, 16) as u64;
+    if count != u16::MAX as u64 && size != u32::MAX as u64 && offset != u32::MAX as u64 {
+        return Ok((count, size, offset));
+    }
+
+    let mut locator = [0; ZIP64_EOCD_LOCATOR_SIZE as usize];
+    let locator_offset = eocd_offset
+        .checked_sub(ZIP64_EOCD_LOCATOR_SIZE)
+        .ok_or(Error::InvalidArchive("ZIP64 locator not found"))?;
+    file.read_exact_at(&mut locator, locator_offset)?;
+    if u32_at(&locator, 0) != ZIP64_EOCD_LOCATOR_SIGNATURE {
+        return Err(Error::InvalidArchive("ZIP64 locator not found"));
+    }
+    let mut record = [0; 56];
+    file.read_exact_at(&mut record, u64_at(&locator, 8))?;
+    if u32_at(&record, 0) != ZIP64_EOCD_SIGNATURE {
+        return Err(Error::InvalidArchive("bad ZIP64 end of central directory"));
+    }
+    Ok((
+        u64_at(&record, 32),
+        u64_at(&record, 40),
+        u64_at(&record, 48),
+    ))
+}
+
+/// The data of the ZIP64 extended information field in `extra`, or nothing if there is none.
+fn zip64_extra(mut extra: &[u8]) -> &[u8] {
+    while extra.len() >= 4 {
+        let id = u16_at(extra, 0);
+        let len = (u16_at(extra, 2) as usize).min(extra.len() - 4);
+        if id == ZIP64_EXTRA_ID {
+            return &extra[4..4 + len];
+        }
+        extra = &extra[4 + len..];
+    }
+    &[]
+}
+
+fn u16_at(data: &[u8], pos: usize) -> u16 {
+    u16::from_le_bytes([data[pos], data[pos + 1]])
+}
+
+fn u32_at(data: &[u8], pos: usize) -> u32 {
+    u32::from_le_bytes(data[pos..pos + 4].try_into().unwrap())
+}
+
+fn u64_at(data: &[u8], pos: usize) -> u64 {
+    u64::from_le_bytes(data[pos..pos + 8].try_into().unwrap())
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// Builds a zip archive of `(name, method, data)` entries, padding the local header extra
+    /// field so that the data of every entry starts at a page boundary.
+    pub(crate) fn build_zip(entries: &[(&str, u16, &[u8])]) -> Vec<u8> {
+        let page = page_size();
+        let mut zip = Vec::new();
+        let mut central = Vec::new();
+        for &(name, method, data) in entries {
+            let offset = zip.len();
+            let header_end = offset + LOCAL_HEADER_SIZE + name.len();
+            let extra_len = header_end.next_multiple_of(page) - header_end;
+            zip.extend(LOCAL_HEADER_SIGNATURE.to_le_bytes());
+            zip.extend([0; 4]);
+            zip.extend(method.to_le_bytes());
+            zip.extend([0; 8]);
+            zip.extend((data.len() as u32).to_le_bytes());
+            zip.extend((data.len() as u32).to_le_bytes());
+            zip.extend((name.len() as u16).to_le_bytes());
+            zip.extend((extra_len as u16).to_le_bytes());
+            zip.extend(name.as_bytes());
+            zip.resize(zip.len() + extra_len, 0);
+            zip.extend(data);
+
+            central.extend(CENTRAL_HEADER_SIGNATURE.to_le_bytes());
+            central.extend([0; 6]);
+            central.extend(method.to_le_bytes());
+            central.extend([0; 8]);
+            central.extend((data.len() as u32).to_le_bytes());
+            central.extend((data.len() as u32).to_le_bytes());
+            central.extend((name.len() as u16).to_le_bytes());
+            central.extend([0; 12]);
+            central.extend((offset as u32).to_le_bytes());
+            central.extend(name.as_bytes());
+        }
+        let directory_offset = zip.len();
+        zip.extend(&central);
+        zip.extend(EOCD_SIGNATURE.to_le_bytes());
+        zip.extend([0; 4]);
+        zip.extend((entries.len() as u16).to_le_bytes());
+        zip.extend((entries.len() as u16).to_le_bytes());
+        zip.extend((central.len() as u32).to_le_bytes());
+        zip.extend((directory_offset as u32).to_le_bytes());
+        zip.extend([0; 2]);
+        zip
+    }
+
+    pub(crate) fn write_temp(name: &str, data: &[u8]) -> std::path::PathBuf {
+        let path = std::env::temp_dir().join(format!("lsplt-rs-{}-{name}", std::process::id()));
+        std::fs::write(&path, data).unwrap();
+        path
+    }
+
+    const LIBRARY: &[u8] = b"\x7fELF\x02\x01\x01\0library";
+
+    #[test]
+    fn finds_stored_entries() {
+        let zip = build_zip(&[
+            ("AndroidManifest.xml", METHOD_STORED, b"<manifest/>"),
+            ("lib/arm64-v8a/libfoo.so", METHOD_STORED, LIBRARY),
+            ("classes.dex", 8, b"deflated"),
+        ]);
+        let path = write_temp("stored.zip", &zip);
+
+        let names: Vec<_> = central_directory(&File::open(&path).unwrap())
+            .unwrap()
+            .into_iter()
+            .map(|entry| entry.name)
+            .collect();
+        assert_eq!(
+            names,
+            [
+                "AndroidManifest.xml",
+                "lib/arm64-v8a/libfoo.so",
+                "classes.dex"
+            ]
+        );
+
+        let entry = ArchiveEntry::find(&path, "lib/arm64-v8a/libfoo.so").unwrap();
+        assert_eq!(entry.offset % page_size(), 0);
+        assert_eq!(&zip[entry.offset..][..entry.size], LIBRARY);
+
+        assert!(matches!(
+            ArchiveEntry::find(&path, "classes.dex"),
+            Err(Error::UnsupportedArchiveEntry { .. })
+        ));
+        assert!(matches!(
+            ArchiveEntry::find(&path, "lib/x86/libfoo.so"),
+            Err(Error::ArchiveEntryNotFound { .. })
+        ));
+
+        let (dev, inode) = (entry.dev, entry.inode);
+        let library = find_embedded_library(path.to_str().unwrap(), dev, inode, entry.offset)
+            .unwrap()
+            .unwrap();
+        assert_eq!(library.name, "lib/arm64-v8a/libfoo.so");
+        assert_eq!(library.offset, entry.offset);
+        // The manifest is not a library, and another inode is another file.
+        let path = path.to_str().unwrap();
+        assert_eq!(find_embedded_library(path, dev, inode, 0x10).unwrap(), None);
+        assert_eq!(
+            find_embedded_library(path, dev, inode + 1, entry.offset).unwrap(),
+            None
+        );
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn rejects_malformed_archives() {
+        let path = write_temp("short.zip", b"PK");
+        assert!(matches!(
+            central_directory(&File::open(&path).unwrap()),
+            Err(Error::InvalidArchive(_))
+        ));
+
+        let mut zip = build_zip(&[("lib/libfoo.so", METHOD_STORED, LIBRARY)]);
+        let directory_size = zip.len() - 10;
+        zip[directory_size..][..4].copy_from_slice(&u32::MAX.to_le_bytes());
+        std::fs::write(&path, &zip).unwrap();
+        assert!(matches!(
+            central_directory(&File::open(&path).unwrap()),
+            Err(Error::InvalidArchive(_))
+        ));
+        std::fs::remove_file(path).unwrap();
+    }
+}
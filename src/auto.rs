@@ -0,0 +1,471 @@
+//! Hooks that are kept alive and applied to libraries loaded after they were registered.
+//!
+//! Auto hooks are tracked here together with the modules they were already applied to. New
+//! libraries are found by comparing the loaded modules with the ones already seen, either when
+//! [`rehook`] is called, from a thread polling the maps, or after every `dlopen` once it is
+//! hooked through the same PLT mechanism. The `dlopen` interception hooks are themselves auto
+//! hooks and follow every newly loaded library.
+
+use std::cell::Cell;
+use std::ffi::{c_char, c_int, c_void};
+use std::sync::atomic::{AtomicBool, AtomicPtr, AtomicUsize, Ordering};
+use std::sync::{Mutex, MutexGuard};
+use std::thread;
+use std::time::Duration;
+
+use crate::{Error, HookHandle, HookTarget, LoadedModule, Result};
+
+struct AutoHook {
+    symbol: String,
+    callback: usize,
+    filter: Box<dyn Fn(&LoadedModule) -> bool + Send>,
+    handles: Vec<(LoadedModule, HookHandle)>,
+}
+
+struct State {
+    hooks: Vec<AutoHook>,
+    /// `(dev, inode, offset, base)` of every module that was already considered.
+    seen: Vec<(crate::DeviceId, crate::Inode, usize, usize)>,
+}
+
+static STATE: Mutex<State> = Mutex::new(State {
+    hooks: Vec::new(),
+    seen: Vec::new(),
+});
+
+/// Bumped by every call to [`enable_auto_hook_with`] and [`disable_auto_hook`], which ends the
+/// polling thread of an earlier call.
+static GENERATION: AtomicUsize = AtomicUsize::new(0);
+/// Whether the `dlopen` interception hooks call [`rehook`].
+static INTERCEPT: AtomicBool = AtomicBool::new(false);
+/// Held while registering the `dlopen` interception hooks.
+static INSTALL: Mutex<()> = Mutex::new(());
+/// Whether the interception hook of `dlopen` and `android_dlopen_ext` was registered. Auto hooks
+/// cannot be removed, so each one is registered at most once.
+static DLOPEN_INSTALLED: AtomicBool = AtomicBool::new(false);
+static ANDROID_DLOPEN_EXT_INSTALLED: AtomicBool = AtomicBool::new(false);
+static DLOPEN: AtomicPtr<c_void> = AtomicPtr::new(std::ptr::null_mut());
+static ANDROID_DLOPEN_EXT: AtomicPtr<c_void> = AtomicPtr::new(std::ptr::null_mut());
+
+thread_local! {
+    /// Set while this thread updates the auto hooks, so a `dlopen` from inside does not deadlock.
+    static IN_REHOOK: Cell<bool> = const { Cell::new(false) };
+}
+
+fn lock() -> MutexGuard<'static, State> {
+    STATE.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+fn module_id(module: &LoadedModule) -> (crate::DeviceId, crate::Inode, usize, usize) {
+    (module.dev(), module.inode(), module.offset(), module.base())
+}
+
+/// Register a hook to a function in every loaded library that references it, now and whenever
+/// a library is loaded later.
+///
+/// This registers the hook like [`register_hook_all`](crate::register_hook_all) and keeps it in
+/// the auto hook set. [`rehook`] applies the set to libraries mapped since, which happens
+/// automatically once [`enable_auto_hook`] was called.
+///
+/// # Arguments
+/// * `symbol` - The function symbol to hook.
+/// * `callback` - The callback function pointer to call when the function is called.
+/// * `filter` - Decides whether a module is hooked. It is called for every module as it is
+///   discovered, so it must not register hooks itself.
+///
+/// # Returns
+/// `Ok(())` if the hook was successfully registered, or an [`Error`](crate::Error) on failure.
+///
+/// # Notes
+/// - This function is thread-safe.
+/// - The hooks in the currently loaded libraries still need to be applied with
+///   [`commit_hook`](crate::commit_hook).
+/// - Auto hooks cannot be removed. To call the original function from the callback, exclude
+///   your own library with [`LoadedModule::is_self`] and call the function directly.
+///
+/// # See Also
+/// - [`enable_auto_hook`]
+/// - [`rehook`]
+pub fn register_hook_auto(
+    symbol: &str,
+    callback: impl HookTarget,
+    filter: impl Fn(&LoadedModule) -> bool + Send + 'static,
+) -> Result<()> {
+    let _guard = RehookGuard::enter();
+    let mut state = lock();
+    let mut hook = AutoHook {
+        symbol: symbol.to_owned(),
+        callback: callback.into_callback() as usize,
+        filter: Box::new(filter),
+        handles: Vec::new(),
+    };
+    apply(&mut hook, LoadedModule::scan_self().iter().collect())?;
+    state.hooks.push(hook);
+    Ok(())
+}
+
+/// Apply the auto hooks to every library mapped since the last call, and commit.
+///
+/// This is called automatically once [`enable_auto_hook`] was called, but can also be called
+/// directly, e.g. right after loading a library, instead of enabling auto hooking at all.
+///
+/// # Returns
+/// `Ok(())` if the hooks were applied, or the first [`Error`](crate::Error) on failure.
+///
+/// # Notes
+/// - This function is thread-safe.
+/// - Like [`commit_hook`](crate::commit_hook), this commits every pending registration, not only
+///   the auto hooks.
+pub fn rehook() -> Result<()> {
+    let Some(_guard) = RehookGuard::try_enter() else {
+        return Ok(());
+    };
+    let mut state = lock();
+    let modules = LoadedModule::scan_self();
+    let mut new = Vec::new();
+    for module in &modules {
+        let id = module_id(module);
+        if !state.seen.contains(&id) {
+            state.seen.push(id);
+            new.push(module);
+        }
+    }
+    // Forget modules that were unloaded, so a library mapped at the same place is considered
+    // again. Their hooks are gone with them, so the handles are discarded without restoring.
+    let ids: Vec<_> = modules.iter().map(module_id).collect();
+    state.seen.retain(|id| ids.contains(id));
+    for hook in &mut state.hooks {
+        let (live, gone): (Vec<_>, Vec<_>) = std::mem::take(&mut hook.handles)
+            .into_iter()
+            .partition(|(module, _)| ids.contains(&module_id(module)));
+        hook.handles = live;
+        gone.into_iter().for_each(|(_, handle)| handle.discard());
+    }
+
+    let mut result = Ok(());
+    for hook in &mut state.hooks {
+        if let Err(err) = apply(hook, new.clone()) {
+            result = result.and(Err(err));
+        }
+    }
+    drop(state);
+    result.and(crate::commit_hook())
+}
+
+/// Register the hook of `hook` in every module of `modules` it applies to.
+fn apply(hook: &mut AutoHook, modules: Vec<&LoadedModule>) -> Result<()> {
+    for module in modules {
+        if hook
+            .handles
+            .iter()
+            .any(|(found, _)| found.is_same_library(module))
+            || !module.references_symbol(&hook.symbol)
+            || !(hook.filter)(module)
+        {
+            continue;
+        }
+        let handle =
+            crate::register_module_hook_scoped(module, &hook.symbol, hook.callback as *mut c_void)?;
+        hook.handles.push((module.clone(), handle));
+    }
+    Ok(())
+}
+
+/// How [`enable_auto_hook_with`] finds libraries loaded after the auto hooks were registered.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum AutoHookMode {
+    /// Compare the loaded modules with the ones already seen every `interval`, from a thread
+    /// started for this purpose, and call [`rehook`] when they differ.
+    ///
+    /// A library can call the hooked functions for up to `interval` after it was loaded, e.g.
+    /// from its constructors, before the auto hooks are applied to it. This is the default,
+    /// polling every 500 milliseconds.
+    Poll(Duration),
+    /// Hook `dlopen` and `android_dlopen_ext` in every loaded library except the dynamic linker
+    /// and this one, and call [`rehook`] after every library that was loaded successfully.
+    ///
+    /// The hooks call `dlopen` from this library instead of the original caller. On Android,
+    /// this changes the linker namespace libraries are loaded in, just like any other `dlopen`
+    /// hook. With glibc, relative names are searched in the `DT_RUNPATH` of this library rather
+    /// than of the caller, and `$ORIGIN` expands to the directory of this library. The hooks stay
+    /// registered after [`disable_auto_hook`], but no longer call [`rehook`].
+    InterceptDlopen,
+}
+
+impl Default for AutoHookMode {
+    fn default() -> Self {
+        AutoHookMode::Poll(Duration::from_millis(500))
+    }
+}
+
+/// Keep the auto hooks applied to libraries loaded from now on, polling for them in the
+/// [default](AutoHookMode::default) mode.
+///
+/// This is the same as [`enable_auto_hook_with`] with [`AutoHookMode::default()`].
+///
+/// # See Also
+/// - [`register_hook_auto`]
+/// - [`disable_auto_hook`]
+pub fn enable_auto_hook() -> Result<()> {
+    enable_auto_hook_with(AutoHookMode::default())
+}
+
+/// Keep the auto hooks applied to libraries loaded from now on, finding them as `mode` says.
+///
+/// This replaces the mode of an earlier call, and calls [`rehook`] once right away. Calling it
+/// again after [`disable_auto_hook`] resumes rehooking.
+///
+/// # Returns
+/// `Ok(())` if auto hooking was enabled and the current libraries were rehooked, or the first
+/// [`Error`](crate::Error) on failure. If auto hooking could not be enabled, it is disabled.
+///
+/// # Notes
+/// - This function is thread-safe.
+/// - Like [`rehook`], every automatic rehook commits every pending registration, not only the
+///   auto hooks.
+/// - Without calling this, [`rehook`] can still be called whenever a library may have been
+///   loaded.
+///
+/// # See Also
+/// - [`register_hook_auto`]
+/// - [`disable_auto_hook`]
+pub fn enable_auto_hook_with(mode: AutoHookMode) -> Result<()> {
+    let generation = GENERATION.fetch_add(1, Ordering::AcqRel) + 1;
+    INTERCEPT.store(false, Ordering::Release);
+    match mode {
+        AutoHookMode::Poll(interval) => {
+            thread::Builder::new()
+                .name("lsplt-rehook".to_owned())
+                .spawn(move || poll(generation, interval))
+                .map_err(Error::Io)?;
+        }
+        AutoHookMode::InterceptDlopen => {
+            install_interception()?;
+            INTERCEPT.store(true, Ordering::Release);
+        }
+    }
+    rehook()
+}
+
+/// Stop applying the auto hooks to libraries loaded from now on.
+///
+/// Hooks that were already applied stay in place, and [`rehook`] can still be called directly.
+pub fn disable_auto_hook() {
+    GENERATION.fetch_add(1, Ordering::AcqRel);
+    INTERCEPT.store(false, Ordering::Release);
+}
+
+/// Calls [`rehook`] whenever the loaded modules changed, until auto hooking is enabled again or
+/// disabled.
+fn poll(generation: usize, interval: Duration) {
+    loop {
+        thread::sleep(interval);
+        if GENERATION.load(Ordering::Acquire) != generation {
+            return;
+        }
+        if modules_changed() {
+            let _ = rehook();
+        }
+    }
+}
+
+/// Whether a module was loaded or unloaded since the last [`rehook`].
+fn modules_changed() -> bool {
+    let ids: Vec<_> = LoadedModule::scan_self().iter().map(module_id).collect();
+    let state = lock();
+    ids.iter().any(|id| !state.seen.contains(id)) || state.seen.iter().any(|id| !ids.contains(id))
+}
+
+/// Registers the `dlopen` interception hooks that are not registered yet.
+fn install_interception() -> Result<()> {
+    let _install = INSTALL.lock().unwrap_or_else(|e| e.into_inner());
+    let filter = |module: &LoadedModule| !module.is_self() && !module.is_linker();
+    // SAFETY: both symbol names are nul-terminated.
+    let (dlopen, android_dlopen_ext) = unsafe {
+        (
+            libc::dlsym(libc::RTLD_DEFAULT, c"dlopen".as_ptr()),
+            libc::dlsym(libc::RTLD_DEFAULT, c"android_dlopen_ext".as_ptr()),
+        )
+    };
+    if !dlopen.is_null() && !DLOPEN_INSTALLED.load(Ordering::Acquire) {
+        DLOPEN.store(dlopen, Ordering::Release);
+        register_hook_auto("dlopen", dlopen_hook as *mut c_void, filter)?;
+        DLOPEN_INSTALLED.store(true, Ordering::Release);
+    }
+    if !android_dlopen_ext.is_null() && !ANDROID_DLOPEN_EXT_INSTALLED.load(Ordering::Acquire) {
+        ANDROID_DLOPEN_EXT.store(android_dlopen_ext, Ordering::Release);
+        register_hook_auto(
+            "android_dlopen_ext",
+            android_dlopen_ext_hook as *mut c_void,
+            filter,
+        )?;
+        ANDROID_DLOPEN_EXT_INSTALLED.store(true, Ordering::Release);
+    }
+    Ok(())
+}
+
+unsafe extern "C" fn dlopen_hook(filename: *const c_char, flags: c_int) -> *mut c_void {
+    // SAFETY: `DLOPEN` was resolved from `dlsym` before this hook was registered.
+    let original = unsafe {
+        std::mem::transmute::<*mut c_void, unsafe extern "C" fn(*const c_char, c_int) -> *mut c_void>(
+            DLOPEN.load(Ordering::Acquire),
+        )
+    };
+    let handle = unsafe { original(filename, flags) };
+    if !handle.is_null() && INTERCEPT.load(Ordering::Acquire) {
+        let _ = rehook();
+    }
+    handle
+}
+
+unsafe extern "C" fn android_dlopen_ext_hook(
+    filename: *const c_char,
+    flags: c_int,
+    extinfo: *const c_void,
+) -> *mut c_void {
+    // SAFETY: `ANDROID_DLOPEN_EXT` was resolved from `dlsym` before this hook was registered.
+    let original = unsafe {
+        std::mem::transmute::<
+            *mut c_void,
+            unsafe extern "C" fn(*const c_char, c_int, *const c_void) -> *mut c_void,
+        >(ANDROID_DLOPEN_EXT.load(Ordering::Acquire))
+    };
+    let handle = unsafe { original(filename, flags, extinfo) };
+    if !handle.is_null() && INTERCEPT.load(Ordering::Acquire) {
+        let _ = rehook();
+    }
+    handle
+}
+
+/// Marks the current thread as updating the auto hooks, and restores the previous state when
+/// dropped, so guards can nest.
+struct RehookGuard {
+    previous: bool,
+}
+
+impl RehookGuard {
+    fn enter() -> Self {
+        let previous = IN_REHOOK.with(|flag| flag.replace(true));
+        RehookGuard { previous }
+    }
+
+    fn try_enter() -> Option<Self> {
+        IN_REHOOK
+            .with(|flag| !flag.replace(true))
+            .then_some(RehookGuard { previous: false })
+    }
+}
+
+impl Drop for RehookGuard {
+    fn drop(&mut self) {
+        IN_REHOOK.with(|flag| flag.set(self.previous));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ffi::CString;
+    use std::fs;
+    use std::path::{Path, PathBuf};
+    use std::time::Instant;
+
+    use super::*;
+    use crate::elf::Elf;
+    use crate::testing;
+
+    extern "C" fn fake_abort() {
+        unsafe { libc::abort() }
+    }
+
+    fn copies() -> PathBuf {
+        std::env::temp_dir().join(format!("lsplt-rs-auto-{}", std::process::id()))
+    }
+
+    /// Registers an auto hook of `abort` in the copy of libgcc_s named `name`.
+    fn register(name: &'static str) {
+        let path = copies().join(name);
+        register_hook_auto("abort", fake_abort as *mut c_void, move |module| {
+            Path::new(module.pathname()) == path
+        })
+        .unwrap();
+        crate::commit_hook().unwrap();
+    }
+
+    /// Loads a copy of libgcc_s named `name`, which is a library loaded after the auto hooks
+    /// were registered. It is never unloaded, since the auto hooks cannot be removed.
+    fn load_copy(name: &str) -> Option<LoadedModule> {
+        let libgcc = LoadedModule::scan_self()
+            .into_iter()
+            .find(|module| module.pathname().contains("/libgcc_s.") && module.offset() == 0)?;
+        let path = copies().join(name);
+        fs::create_dir_all(copies()).unwrap();
+        fs::copy(libgcc.pathname(), &path).unwrap();
+        let c_path = CString::new(path.to_str().unwrap()).unwrap();
+        let handle = unsafe { libc::dlopen(c_path.as_ptr(), libc::RTLD_NOW) };
+        fs::remove_file(&path).unwrap();
+        fs::remove_dir(copies()).unwrap();
+        assert!(!handle.is_null());
+        LoadedModule::scan_self()
+            .into_iter()
+            .find(|module| module.inode() != libgcc.inode() && module.pathname().contains(name))
+    }
+
+    fn is_hooked(module: &LoadedModule) -> bool {
+        let slots = unsafe { Elf::from_base(module.base()) }
+            .unwrap()
+            .find_plt_addr("abort");
+        !slots.is_empty()
+            && slots.iter().all(|&slot| unsafe {
+                std::ptr::read_volatile(slot as *const usize) == fake_abort as *const () as usize
+            })
+    }
+
+    #[test]
+    fn rehooks_on_demand() {
+        let _lock = testing::lock();
+        disable_auto_hook();
+        register("on-demand.so");
+        let Some(module) = load_copy("on-demand.so") else {
+            return;
+        };
+        assert!(!is_hooked(&module));
+        rehook().unwrap();
+        assert!(is_hooked(&module));
+    }
+
+    #[test]
+    fn polls_for_new_libraries() {
+        let _lock = testing::lock();
+        enable_auto_hook_with(AutoHookMode::Poll(Duration::from_millis(10))).unwrap();
+        register("poll.so");
+        let module = load_copy("poll.so");
+        let deadline = Instant::now() + Duration::from_secs(10);
+        let hooked = module.map(|module| {
+            while !is_hooked(&module) && Instant::now() < deadline {
+                thread::sleep(Duration::from_millis(10));
+            }
+            is_hooked(&module)
+        });
+        disable_auto_hook();
+        assert_ne!(hooked, Some(false));
+    }
+
+    #[test]
+    fn intercepts_dlopen_once() {
+        let _lock = testing::lock();
+        for _ in 0..2 {
+            enable_auto_hook_with(AutoHookMode::InterceptDlopen).unwrap();
+            disable_auto_hook();
+        }
+        let state = lock();
+        assert_eq!(
+            state
+                .hooks
+                .iter()
+                .filter(|hook| hook.symbol == "dlopen")
+                .count(),
+            1
+        );
+    }
+}
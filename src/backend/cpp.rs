@@ -0,0 +1,86 @@
+//! Backend forwarding to the LSPlt C++ core.
+
+use std::ffi::{c_void, CString};
+
+use crate::{DeviceId, Error, Inode, Result};
+
+/// Converts the result of a wrapper call into an [`Error`] using `lsplt_last_error()`.
+///
+/// `failed` is the error to report when LSPlt itself returned `false`.
+fn check(result: bool, failed: impl FnOnce() -> Error) -> Result<()> {
+    if result {
+        return Ok(());
+    }
+    match unsafe { lsplt_sys::lsplt_last_error() } as u32 {
+        lsplt_sys::LSPLT_ERROR_INVALID_ARGUMENT => Err(Error::InvalidArgument("rejected by LSPlt")),
+        lsplt_sys::LSPLT_ERROR_EXCEPTION => Err(Error::CppException),
+        lsplt_sys::LSPLT_ERROR_OUT_OF_MEMORY => {
+            Err(Error::Io(std::io::ErrorKind::OutOfMemory.into()))
+        }
+        _ => Err(failed()),
+    }
+}
+
+/// LSPlt matches symbols by name only, so `name@VERSION` would never be found.
+fn check_unversioned(symbol: &str) -> Result<()> {
+    if symbol.contains('@') {
+        return Err(Error::InvalidArgument(
+            "versioned symbols need the `pure-rust` backend",
+        ));
+    }
+    Ok(())
+}
+
+pub(crate) fn register_hook(
+    dev: DeviceId,
+    inode: Inode,
+    symbol: &str,
+    callback: *mut c_void,
+    backup: *mut *mut c_void,
+) -> Result<()> {
+    check_unversioned(symbol)?;
+    let c_symbol = CString::new(symbol)?;
+    let result =
+        unsafe { lsplt_sys::lsplt_register_hook(dev, inode, c_symbol.as_ptr(), callback, backup) };
+    check(result, || Error::InvalidArgument("rejected by LSPlt"))
+}
+
+pub(crate) fn register_hook_with_offset(
+    dev: DeviceId,
+    inode: Inode,
+    offset: usize,
+    size: usize,
+    symbol: &str,
+    callback: *mut c_void,
+    backup: *mut *mut c_void,
+) -> Result<()> {
+    check_unversioned(symbol)?;
+    let c_symbol = CString::new(symbol)?;
+    let result = unsafe {
+        lsplt_sys::lsplt_register_hook_with_offset(
+            dev,
+            inode,
+            offset,
+            size,
+            c_symbol.as_ptr(),
+            callback,
+            backup,
+        )
+    };
+    check(result, || Error::InvalidArgument("rejected by LSPlt"))
+}
+
+// LSPlt only fails to commit or invalidate when remapping a hooked region fails, in which case
+// errno still holds the reason.
+
+pub(crate) fn commit_hook() -> Result<()> {
+    let result = unsafe { lsplt_sys::lsplt_commit_hook() };
+    let os_error = std::io::Error::last_os_error();
+    check(result, || Error::MemoryProtection(os_error))
+}
+
+pub(crate) fn invalidate_backup() -> Result<()> {
+    let result = unsafe { lsplt_sys::lsplt_invalidate_backup() };
+    let os_error = std::io::Error::last_os_error();
+    check(result, || Error::MemoryProtection(os_error))
+}
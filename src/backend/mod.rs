@@ -0,0 +1,17 @@
+//! The hooking engine behind the public API.
+//!
+//! By default the LSPlt C++ core is used through `lsplt-sys`. With the `pure-rust` feature the
+//! engine is reimplemented in Rust and the C++ toolchain is not needed at all.
+
+#[cfg(not(any(feature = "cpp", feature = "pure-rust")))]
+compile_error!("either the `cpp` or the `pure-rust` feature of lsplt-rs must be enabled");
+
+#[cfg(not(feature = "pure-rust"))]
+mod cpp;
+#[cfg(not(feature = "pure-rust"))]
+pub(crate) use cpp::*;
+
+#[cfg(feature = "pure-rust")]
+mod native;
+#[cfg(feature = "pure-rust")]
+pub(crate) use native::*;
@@ -0,0 +1,546 @@
+//! Pure-Rust reimplementation of the LSPlt core.
+//!
+//! The bookkeeping follows `lsplt.cc` closely: registrations are queued until commit, every
+//! matching mapping is tracked in a [`HookInfo`], and GOT slots of foreign modules are patched on
+//! an anonymous copy of their mapping so the original file-backed pages stay clean and can be
+//! restored once all hooks of that mapping are removed.
+//!
+//! It deviates from `lsplt.cc` in two places:
+//!
+//! - Mappings that are already writable are patched in place, like the module this crate is
+//!   linked into. LSPlt copies them as well, but a writable mapping may hold data next to the
+//!   GOT, and every write to that data after the hook would be lost once the file-backed pages
+//!   are put back.
+//! - The anonymous copy is filled before it replaces the original mapping, so the range is never
+//!   left unmapped or empty while other threads may run code from it.
+
+use std::collections::BTreeMap;
+use std::ffi::c_void;
+use std::sync::Mutex;
+
+use crate::elf::Elf;
+use crate::{DeviceId, Error, Inode, MapInfo, Perms, Result};
+
+struct RegisterInfo {
+    dev: DeviceId,
+    inode: Inode,
+    offset_range: (usize, usize),
+    symbol: String,
+    callback: usize,
+    backup: usize,
+}
+
+struct HookInfo {
+    map: MapInfo,
+    /// GOT slot address -> value it held before it was first hooked.
+    hooks: BTreeMap<usize, usize>,
+    /// Address of the original mapping while the hooked anonymous copy is in place.
+    backup: usize,
+    elf: Option<Elf>,
+    /// Whether the mapping belongs to the module this crate is linked into.
+    is_self: bool,
+}
+
+impl HookInfo {
+    fn matches(&self, info: &RegisterInfo) -> bool {
+        info.dev == self.map.dev
+            && info.inode == self.map.inode
+            && self.map.offset >= info.offset_range.0
+            && self.map.offset < info.offset_range.1
+    }
+
+    fn len(&self) -> usize {
+        self.map.end - self.map.start
+    }
+}
+
+struct State {
+    register_info: Vec<RegisterInfo>,
+    /// Keyed by the start address of the mapping.
+    hook_info: BTreeMap<usize, HookInfo>,
+}
+
+static STATE: Mutex<State> = Mutex::new(State {
+    register_info: Vec::new(),
+    hook_info: BTreeMap::new(),
+});
+
+fn lock() -> std::sync::MutexGuard<'static, State> {
+    STATE.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+pub(crate) fn register_hook(
+    dev: DeviceId,
+    inode: Inode,
+    symbol: &str,
+    callback: *mut c_void,
+    backup: *mut *mut c_void,
+) -> Result<()> {
+    register(dev, inode, (0, usize::MAX), symbol, callback, backup)
+}
+
+pub(crate) fn register_hook_with_offset(
+    dev: DeviceId,
+    inode: Inode,
+    offset: usize,
+    size: usize,
+    symbol: &str,
+    callback: *mut c_void,
+    backup: *mut *mut c_void,
+) -> Result<()> {
+    let end = offset
+        .checked_add(size)
+        .ok_or(Error::InvalidOffset { offset, size })?;
+    register(dev, inode, (offset, end), symbol, callback, backup)
+}
+
+/// Queues a registration. The arguments were checked by
+/// [`Registration::validate`](crate::registry::Registration::validate).
+fn register(
+    dev: DeviceId,
+    inode: Inode,
+    offset_range: (usize, usize),
+    symbol: &str,
+    callback: *mut c_void,
+    backup: *mut *mut c_void,
+) -> Result<()> {
+    lock().register_info.push(RegisterInfo {
+        dev,
+        inode,
+        offset_range,
+        symbol: symbol.to_owned(),
+        callback: callback as usize,
+        backup: backup as usize,
+    });
+    Ok(())
+}
+
+pub(crate) fn commit_hook() -> Result<()> {
+    let mut state = lock();
+    if state.register_info.is_empty() {
+        return Ok(());
+    }
+
+    let mut new_hook_info = scan_hook_info()?;
+    new_hook_info.retain(|_, info| state.register_info.iter().any(|reg| info.matches(reg)));
+
+    // Mappings replaced by an anonymous copy no longer show their path, so carry them over.
+    let old_hook_info = std::mem::take(&mut state.hook_info);
+    for (start, info) in old_hook_info {
+        if info.backup != 0 {
+            new_hook_info.remove(&info.backup);
+        }
+        if let Some(entry) = new_hook_info.get_mut(&start) {
+            *entry = info;
+        } else if info.backup != 0 {
+            new_hook_info.insert(start, info);
+        }
+    }
+    state.hook_info = new_hook_info;
+
+    let State {
+        register_info,
+        hook_info,
+    } = &mut *state;
+
+    let mut result = Ok(());
+    let mut pending = Vec::new();
+    for info in hook_info.values_mut() {
+        register_info.retain(|reg| {
+            if info.map.offset != reg.offset_range.0 || !info.matches(reg) {
+                return true;
+            }
+            if info.elf.is_none() {
+                info.elf = unsafe { Elf::from_base(info.map.start) };
+            }
+            // Missing ELF images and symbols are diagnosed by the commit report.
+            let addrs = match &info.elf {
+                Some(elf) => elf.find_plt_addr(&reg.symbol),
+                None => Vec::new(),
+            };
+            for addr in addrs {
+                pending.push((addr, reg.callback, reg.backup));
+            }
+            false
+        });
+    }
+
+    for (addr, callback, backup) in pending {
+        keep_first(
+            &mut result,
+            do_hook(hook_info, addr, callback, backup as *mut usize),
+        );
+    }
+    result
+}
+
+/// Stores `next` into `result` unless `result` already holds an error.
+fn keep_first(result: &mut Result<()>, next: Result<()>) {
+    if result.is_ok() {
+        *result = next;
+    }
+}
+
+pub(crate) fn invalidate_backup() -> Result<()> {
+    let mut state = lock();
+    let mut result = Ok(());
+    for info in state.hook_info.values_mut() {
+        if info.backup == 0 {
+            continue;
+        }
+        // The hooked values are kept since the original mapping will be patched directly.
+        for (&addr, value) in info.hooks.iter_mut() {
+            *value = unsafe { std::ptr::read_volatile(addr as *const usize) };
+        }
+        let len = info.len();
+        if let Err(err) = unsafe { sys_mremap(info.backup, len, info.map.start) } {
+            keep_first(&mut result, Err(Error::MemoryProtection(err)));
+            info.hooks.clear();
+            continue;
+        }
+        unsafe {
+            if libc::mprotect(info.map.start as *mut c_void, len, libc::PROT_WRITE) == 0 {
+                for (&addr, &value) in &info.hooks {
+                    std::ptr::write_volatile(addr as *mut usize, value);
+                }
+                libc::mprotect(info.map.start as *mut c_void, len, info.map.perms.prot());
+            }
+        }
+        info.hooks.clear();
+        info.backup = 0;
+    }
+    result
+}
+
+/// Scans the maps of the current process for mappings that may hold an ELF header or GOT.
+fn scan_hook_info() -> Result<BTreeMap<usize, HookInfo>> {
+    let maps = MapInfo::try_scan("self")?;
+    let anchor = scan_hook_info as fn() -> Result<BTreeMap<usize, HookInfo>> as usize;
+    let self_id = maps
+        .iter()
+        .find(|map| map.start <= anchor && anchor < map.end)
+        .map(|map| (map.dev, map.inode));
+
+    Ok(maps
+        .into_iter()
+        .filter(|map| {
+            map.perms.is_private()
+                && map.perms.is_readable()
+                && map
+                    .pathname
+                    .as_deref()
+                    .is_some_and(|path| !path.starts_with('['))
+        })
+        .map(|map| {
+            let is_self = self_id == Some((map.dev, map.inode));
+            (
+                map.start,
+                HookInfo {
+                    map,
+                    hooks: BTreeMap::new(),
+                    backup: 0,
+                    elf: None,
+                    is_self,
+                },
+            )
+        })
+        .collect())
+}
+
+fn do_hook(
+    hook_info: &mut BTreeMap<usize, HookInfo>,
+    addr: usize,
+    callback: usize,
+    backup: *mut usize,
+) -> Result<()> {
+    let info = hook_info
+        .range_mut(..=addr)
+        .next_back()
+        .map(|(_, info)| info)
+        .filter(|info| addr < info.map.end)
+        .ok_or_else(|| {
+            Error::MemoryProtection(std::io::Error::new(
+                std::io::ErrorKind::NotFound,
+                format!("GOT slot {addr:#x} is not in a private readable mapping"),
+            ))
+        })?;
+    let len = info.len();
+
+    // Unlike LSPlt, writable mappings are patched in place, see the module documentation.
+    let in_place = info.is_self || info.map.perms.is_writable();
+
+    unsafe {
+        if info.backup == 0 && !in_place {
+            // Move the file-backed pages aside and hook on an anonymous copy instead. The copy is
+            // filled before the original is replaced, so the range is only unmapped between the
+            // two `mremap` calls and no code reads the pages while they are missing.
+            let copy = sys_mmap(
+                0,
+                len,
+                libc::PROT_READ | libc::PROT_WRITE | info.map.perms.prot(),
+                libc::MAP_ANONYMOUS,
+            )
+            .map_err(Error::MemoryProtection)?;
+            std::ptr::copy_nonoverlapping(info.map.start as *const u8, copy as *mut u8, len);
+            let backup_addr = match sys_mmap(0, len, libc::PROT_NONE, libc::MAP_ANONYMOUS) {
+                Ok(addr) => addr,
+                Err(err) => {
+                    libc::munmap(copy as *mut c_void, len);
+                    return Err(Error::MemoryProtection(err));
+                }
+            };
+            if let Err(err) = sys_mremap(info.map.start, len, backup_addr) {
+                libc::munmap(copy as *mut c_void, len);
+                libc::munmap(backup_addr as *mut c_void, len);
+                return Err(Error::MemoryProtection(err));
+            }
+            if let Err(err) = sys_mremap(copy, len, info.map.start) {
+                let _ = sys_mremap(backup_addr, len, info.map.start);
+                libc::munmap(copy as *mut c_void, len);
+                return Err(Error::MemoryProtection(err));
+            }
+            info.backup = backup_addr;
+        }
+        if info.is_self && !info.map.perms.is_writable() {
+            // Our own module is always dirty, so there is nothing to back up.
+            if libc::mprotect(
+                info.map.start as *mut c_void,
+                len,
+                (info.map.perms | Perms::WRITE).prot(),
+            ) != 0
+            {
+                return Err(Error::MemoryProtection(std::io::Error::last_os_error()));
+            }
+            info.map.perms |= Perms::WRITE;
+        }
+
+        let slot = addr as *mut usize;
+        let original = std::ptr::read_volatile(slot);
+        if original != callback {
+            // The backup is written first so it is valid once the callback can be reached.
+            if !backup.is_null() {
+                *backup = original;
+            }
+            std::ptr::write_volatile(slot, callback);
+        }
+
+        match info.hooks.get(&addr) {
+            Some(&value) => {
+                if value == callback {
+                    info.hooks.remove(&addr);
+                }
+            }
+            None => {
+                info.hooks.insert(addr, original);
+            }
+        }
+
+        if info.hooks.is_empty() && info.backup != 0 {
+            sys_mremap(info.backup, len, info.map.start).map_err(Error::MemoryProtection)?;
+            info.backup = 0;
+        }
+    }
+    Ok(())
+}
+
+// Thin wrappers around the `mmap` and `mremap` system calls that report failures as `io::Error`.
+
+unsafe fn sys_mmap(addr: usize, len: usize, prot: i32, flags: i32) -> std::io::Result<usize> {
+    #[cfg(target_pointer_width = "64")]
+    let nr = libc::SYS_mmap;
+    #[cfg(target_pointer_width = "32")]
+    let nr = libc::SYS_mmap2;
+    let result = libc::syscall(
+        nr,
+        addr as libc::c_long,
+        len as libc::c_long,
+        prot as libc::c_long,
+        (libc::MAP_PRIVATE | flags) as libc::c_long,
+        -1 as libc::c_long,
+        0 as libc::c_long,
+    ) as usize;
+    if result == libc::MAP_FAILED as usize {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(result)
+}
+
+unsafe fn sys_mremap(old: usize, len: usize, new: usize) -> std::io::Result<()> {
+    let result = libc::syscall(
+        libc::SYS_mremap,
+        old as libc::c_long,
+        len as libc::c_long,
+        len as libc::c_long,
+        (libc::MREMAP_FIXED | libc::MREMAP_MAYMOVE) as libc::c_long,
+        new as libc::c_long,
+    ) as usize;
+    if result == libc::MAP_FAILED as usize {
+        return Err(std::io::Error::last_os_error());
+    }
+    if result != new {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::AddrNotAvailable,
+            format!("mapping moved to {result:#x} instead of {new:#x}"),
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ptr;
+
+    use super::*;
+    use crate::{testing, LoadedModule};
+
+    const FAKE_PID: i32 = 0x7fff_0000;
+
+    extern "C" fn fake_getpid() -> i32 {
+        FAKE_PID
+    }
+
+    extern "C" fn fake_abort() {
+        unsafe { libc::abort() }
+    }
+
+    extern "C" fn fake_cxa_finalize(_dso: *mut c_void) {}
+
+    fn read_slots(slots: &[usize]) -> Vec<usize> {
+        slots
+            .iter()
+            .map(|&slot| unsafe { ptr::read_volatile(slot as *const usize) })
+            .collect()
+    }
+
+    #[test]
+    fn hooks_calls_through_and_restores() {
+        let _lock = testing::lock();
+        let pid = testing::real_pid();
+        let module = testing::test_binary();
+
+        let mut backup = ptr::null_mut();
+        let callback = fake_getpid as *mut c_void;
+        register_hook(
+            module.dev(),
+            module.inode(),
+            "getpid",
+            callback,
+            &mut backup,
+        )
+        .unwrap();
+        commit_hook().unwrap();
+        assert_eq!(testing::getpid(), FAKE_PID);
+        assert!(!backup.is_null());
+        let original: extern "C" fn() -> i32 = unsafe { std::mem::transmute(backup) };
+        assert_eq!(original(), pid);
+
+        // The module is patched in place, so there is no copy to restore.
+        assert!(lock().hook_info.values().all(|info| info.backup == 0));
+
+        let mut hooked = ptr::null_mut();
+        register_hook(module.dev(), module.inode(), "getpid", backup, &mut hooked).unwrap();
+        commit_hook().unwrap();
+        assert_eq!(hooked, callback);
+        assert_eq!(testing::getpid(), pid);
+    }
+
+    /// libgcc_s, skipping the original pages a previous test may have moved aside.
+    fn libgcc() -> Option<LoadedModule> {
+        LoadedModule::scan_self()
+            .into_iter()
+            .find(|module| module.pathname().contains("/libgcc_s.") && module.offset() == 0)
+    }
+
+    fn in_copy(slot: usize) -> bool {
+        MapInfo::scan_self()
+            .iter()
+            .find(|map| map.start <= slot && slot < map.end)
+            .is_some_and(|map| map.pathname.is_none())
+    }
+
+    #[test]
+    fn patches_writable_mappings_in_place() {
+        let _lock = testing::lock();
+        // Without `BIND_NOW`, the PLT slots of libgcc_s stay writable next to its data.
+        let Some(module) = libgcc() else {
+            return;
+        };
+        let slots = unsafe { Elf::from_base(module.base()) }
+            .unwrap()
+            .find_plt_addr("abort");
+        assert!(!slots.is_empty());
+
+        let mut backup = ptr::null_mut();
+        let callback = fake_abort as *mut c_void;
+        register_hook(module.dev(), module.inode(), "abort", callback, &mut backup).unwrap();
+        commit_hook().unwrap();
+        assert!(read_slots(&slots)
+            .iter()
+            .all(|&value| value == callback as usize));
+        assert!(slots.iter().all(|&slot| !in_copy(slot)));
+
+        register_hook(
+            module.dev(),
+            module.inode(),
+            "abort",
+            backup,
+            ptr::null_mut(),
+        )
+        .unwrap();
+        commit_hook().unwrap();
+        assert!(read_slots(&slots)
+            .iter()
+            .all(|&value| value == backup as usize));
+    }
+
+    #[test]
+    fn invalidates_backups() {
+        let _lock = testing::lock();
+        // A foreign library whose GOT entry for `__cxa_finalize` is read-only after relocation,
+        // so it is hooked on an anonymous copy. libgcc_s only calls it when it is unloaded.
+        let Some(module) = libgcc() else {
+            return;
+        };
+        let slots = unsafe { Elf::from_base(module.base()) }
+            .unwrap()
+            .find_plt_addr("__cxa_finalize");
+        assert!(!slots.is_empty());
+
+        let mut backup = ptr::null_mut();
+        let callback = fake_cxa_finalize as *mut c_void;
+        register_hook(
+            module.dev(),
+            module.inode(),
+            "__cxa_finalize",
+            callback,
+            &mut backup,
+        )
+        .unwrap();
+        commit_hook().unwrap();
+        assert!(read_slots(&slots)
+            .iter()
+            .all(|&value| value == callback as usize));
+        assert!(slots.iter().all(|&slot| in_copy(slot)));
+
+        // The hooks are moved onto the original mapping, which stays in place from now on.
+        invalidate_backup().unwrap();
+        assert!(read_slots(&slots)
+            .iter()
+            .all(|&value| value == callback as usize));
+        assert!(slots.iter().all(|&slot| !in_copy(slot)));
+        assert!(lock().hook_info.values().all(|info| info.backup == 0));
+
+        register_hook(
+            module.dev(),
+            module.inode(),
+            "__cxa_finalize",
+            backup,
+            ptr::null_mut(),
+        )
+        .unwrap();
+        commit_hook().unwrap();
+        assert!(read_slots(&slots)
+            .iter()
+            .all(|&value| value == backup as usize));
+    }
+}
@@ -0,0 +1,316 @@
+//! Ordered chains of handlers for one hooked symbol.
+//!
+//! A chain owns the GOT slots of its symbol: only its first handler is registered, and every
+//! link stores the function its handler forwards to. Inserting a handler registers it in front,
+//! while removing one either re-registers its successor or just relinks its predecessor, so the
+//! other handlers never notice.
+
+use std::ffi::c_void;
+use std::marker::PhantomData;
+use std::sync::atomic::{AtomicPtr, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use crate::registry::{self, Registration};
+use crate::report::HookOutcome;
+use crate::{DeviceId, Error, FnPtr, Inode, MapInfo, Result};
+
+/// Identifies the hooked symbol of a chain.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct ChainKey {
+    dev: DeviceId,
+    inode: Inode,
+    offset_range: Option<(usize, usize)>,
+    symbol: String,
+}
+
+#[derive(Debug)]
+struct Link {
+    handler: usize,
+    /// The next handler, or the original function for the last link.
+    next: AtomicPtr<c_void>,
+}
+
+struct Chain {
+    key: ChainKey,
+    /// The first link is the one the GOT points to.
+    links: Vec<Arc<Link>>,
+}
+
+static CHAINS: Mutex<Vec<Chain>> = Mutex::new(Vec::new());
+
+fn lock() -> MutexGuard<'static, Vec<Chain>> {
+    CHAINS.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Ordered chains of handlers for the same symbol.
+///
+/// Registering two hooks for the same symbol with [`register_hook`](crate::register_hook) makes
+/// the second one replace the first, and its backup point to the first hook instead of the real
+/// function. A chain instead keeps every handler: the GOT points to the handler inserted last,
+/// each handler calls the next one through [`ChainLink::next`], and the last one calls the
+/// original function. Any link can be removed at any time without affecting the others.
+///
+/// # Examples
+/// ```no_run
+/// # let (dev, inode) = (0, 0);
+/// use std::sync::OnceLock;
+///
+/// type GetPid = extern "C" fn() -> i32;
+///
+/// static LINK: OnceLock<lsplt_rs::ChainLink<GetPid>> = OnceLock::new();
+///
+/// extern "C" fn my_getpid() -> i32 {
+///     LINK.get().map_or(-1, |link| link.next()()) + 1
+/// }
+///
+/// let link = lsplt_rs::HookChain::insert(dev, inode, "getpid", my_getpid as GetPid)?;
+/// let _ = LINK.set(link);
+/// // ...
+/// LINK.get().unwrap().remove()?;
+/// # Ok::<(), lsplt_rs::Error>(())
+/// ```
+#[derive(Debug)]
+pub struct HookChain;
+
+impl HookChain {
+    /// Inserts `handler` in front of the chain for `symbol` in the library identified by `dev`
+    /// and `inode`, and commits.
+    ///
+    /// # Returns
+    /// The [`ChainLink`] of the handler, or an [`Error`] if the hook could not be applied.
+    ///
+    /// # Notes
+    /// - This function is thread-safe.
+    /// - Unlike [`register_hook`](crate::register_hook), the library must already be mapped,
+    ///   and the hook is committed right away. The commit is a full
+    ///   [`commit_hook`](crate::commit_hook), so it also applies every other pending
+    ///   registration.
+    pub fn insert<F: FnPtr>(
+        dev: DeviceId,
+        inode: Inode,
+        symbol: &str,
+        handler: F,
+    ) -> Result<ChainLink<F>> {
+        Self::insert_key(
+            ChainKey {
+                dev,
+                inode,
+                offset_range: None,
+                symbol: symbol.to_owned(),
+            },
+            handler,
+        )
+    }
+
+    /// Inserts `handler` in front of the chain for `symbol` in the library at `offset` of the
+    /// file identified by `dev` and `inode`, and commits.
+    ///
+    /// See [`insert`](HookChain::insert) and
+    /// [`register_hook_with_offset`](crate::register_hook_with_offset) for details.
+    pub fn insert_with_offset<F: FnPtr>(
+        dev: DeviceId,
+        inode: Inode,
+        offset: usize,
+        size: usize,
+        symbol: &str,
+        handler: F,
+    ) -> Result<ChainLink<F>> {
+        Self::insert_key(
+            ChainKey {
+                dev,
+                inode,
+                offset_range: Some((offset, size)),
+                symbol: symbol.to_owned(),
+            },
+            handler,
+        )
+    }
+
+    fn insert_key<F: FnPtr>(key: ChainKey, handler: F) -> Result<ChainLink<F>> {
+        let mut chains = lock();
+        let handler = handler.to_ptr() as usize;
+        let index = chains.iter().position(|chain| chain.key == key);
+        let next = match index {
+            Some(index) => chains[index].links[0].handler as *mut c_void,
+            None => {
+                let registration = key.registration(handler);
+                registration.validate()?;
+                let slots = registry::resolve_slots(&MapInfo::scan_self(), &registration)
+                    .map_err(|outcome| key.error(outcome))?;
+                // SAFETY: the slot lies in the mapped image of the library.
+                unsafe { std::ptr::read_volatile(slots[0] as *const *mut c_void) }
+            }
+        };
+        // The link is complete before the GOT can reach the handler.
+        let link = Arc::new(Link {
+            handler,
+            next: AtomicPtr::new(next),
+        });
+        key.apply(handler)?;
+
+        match index {
+            Some(index) => chains[index].links.insert(0, link.clone()),
+            None => chains.push(Chain {
+                key: key.clone(),
+                links: vec![link.clone()],
+            }),
+        }
+        Ok(ChainLink {
+            key,
+            link,
+            _marker: PhantomData,
+        })
+    }
+}
+
+impl ChainKey {
+    fn registration(&self, callback: usize) -> Registration {
+        Registration {
+            dev: self.dev,
+            inode: self.inode,
+            offset_range: self.offset_range,
+            symbol: self.symbol.clone(),
+            callback,
+        }
+    }
+
+    fn error(&self, outcome: HookOutcome) -> Error {
+        match outcome {
+            HookOutcome::NotMapped => Error::NotMapped {
+                dev: self.dev,
+                inode: self.inode,
+            },
+            HookOutcome::ElfNotFound => Error::ElfNotFound {
+                dev: self.dev,
+                inode: self.inode,
+            },
+            HookOutcome::SymbolNotFound => Error::SymbolNotFound {
+                symbol: self.symbol.clone(),
+            },
+            HookOutcome::Patched { .. } | HookOutcome::Failed { .. } => {
+                Error::MemoryProtection(std::io::Error::other(format!(
+                    "failed to patch the GOT slots of `{}`",
+                    self.symbol
+                )))
+            }
+        }
+    }
+
+    /// Points the GOT slots of the symbol to `callback` and commits.
+    fn apply(&self, callback: usize) -> Result<()> {
+        let mut pending = registry::pending();
+        let first = pending.len();
+        registry::register(
+            &mut pending,
+            self.registration(callback),
+            std::ptr::null_mut(),
+        )?;
+        let report = registry::commit(&mut pending);
+        match &report.hooks[first].outcome {
+            HookOutcome::Patched { .. } => Ok(()),
+            outcome => Err(self.error(outcome.clone())),
+        }
+    }
+}
+
+/// A handler in a [`HookChain`].
+///
+/// The link stays in the chain until [`remove`](ChainLink::remove) is called; dropping it does
+/// not remove the handler.
+#[derive(Debug)]
+pub struct ChainLink<F: FnPtr> {
+    key: ChainKey,
+    link: Arc<Link>,
+    _marker: PhantomData<F>,
+}
+
+impl<F: FnPtr> ChainLink<F> {
+    /// The function this handler should call to continue the chain: the next handler, or the
+    /// original function if this is the last one.
+    pub fn next(&self) -> F {
+        // SAFETY: `next` is either a handler of the chain or the original function, both of
+        // which have the signature `F`.
+        unsafe { F::from_ptr(self.link.next.load(Ordering::Acquire)) }
+    }
+
+    /// Removes this handler from the chain.
+    ///
+    /// Removing the first handler points the GOT to the next one, or restores the original
+    /// function if it was the only one, and commits. Removing any other handler only relinks its
+    /// predecessor. Calls that are already executing the handler keep working, and removing a
+    /// link twice does nothing. Like [`HookChain::insert`], a commit also applies every other
+    /// pending registration.
+    ///
+    /// # Returns
+    /// `Ok(())` if the handler was removed, or an [`Error`] if the GOT could not be updated, in
+    /// which case the handler stays in the chain.
+    pub fn remove(&self) -> Result<()> {
+        let mut chains = lock();
+        let Some(index) = chains.iter().position(|chain| chain.key == self.key) else {
+            return Ok(());
+        };
+        let chain = &mut chains[index];
+        let Some(position) = chain
+            .links
+            .iter()
+            .position(|link| Arc::ptr_eq(link, &self.link))
+        else {
+            return Ok(());
+        };
+        let next = self.link.next.load(Ordering::Acquire);
+        if position == 0 {
+            chain.key.apply(next as usize)?;
+        } else {
+            chain.links[position - 1]
+                .next
+                .store(next, Ordering::Release);
+        }
+        chain.links.remove(position);
+        if chain.links.is_empty() {
+            chains.remove(index);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+
+    type GetPid = extern "C" fn() -> i32;
+
+    extern "C" fn fake_uid() -> u32 {
+        4242
+    }
+
+    extern "C" fn fake_pid() -> i32 {
+        2333
+    }
+
+    #[test]
+    fn applying_commits_other_pending_hooks() {
+        let _lock = testing::lock();
+        let module = testing::test_binary();
+        let (pid, uid) = (testing::real_pid(), testing::real_uid());
+
+        let mut backup = std::ptr::null_mut();
+        crate::register_hook(
+            module.dev(),
+            module.inode(),
+            "getuid",
+            fake_uid as *mut _,
+            Some(&mut backup),
+        )
+        .unwrap();
+        let link =
+            HookChain::insert(module.dev(), module.inode(), "getpid", fake_pid as GetPid).unwrap();
+        assert_eq!(testing::getpid(), 2333);
+        assert_eq!(testing::getuid(), 4242);
+
+        crate::register_hook(module.dev(), module.inode(), "getuid", backup, None).unwrap();
+        link.remove().unwrap();
+        assert_eq!(testing::getpid(), pid);
+        assert_eq!(testing::getuid(), uid);
+    }
+}
@@ -0,0 +1,491 @@
+//! Hooks whose callback is a closure.
+//!
+//! A GOT slot can only hold a plain function pointer, so every closure hook is given one of
+//! [`SLOT_COUNT`] slots, and the callback registered with the backend is a trampoline that is
+//! monomorphized for the signature and the slot index. The trampoline looks up the closure in its
+//! slot and calls it with the original function.
+
+use std::any::TypeId;
+use std::ffi::c_void;
+use std::marker::PhantomData;
+use std::sync::atomic::{AtomicBool, AtomicPtr, AtomicUsize, Ordering};
+use std::sync::{Arc, OnceLock};
+
+use crate::registry::{self, Registration};
+use crate::{DeviceId, Error, FnPtr, HookHandle, Inode, MapInfo, Result};
+
+/// The maximum number of closure hooks that can exist at the same time.
+pub const SLOT_COUNT: usize = 32;
+
+struct Slot {
+    used: AtomicBool,
+    /// The signature of the hooks using this slot. It is set by the first one and never changes,
+    /// so a call that reaches the trampoline after the slot was reused still finds a
+    /// `SlotData` of its own signature.
+    signature: OnceLock<TypeId>,
+    /// The number of calls currently executing the trampoline of this slot.
+    calls: AtomicUsize,
+    /// A `Box<SlotData<F>>`, or null while the closure is being removed.
+    data: AtomicPtr<()>,
+    /// The original function, for calls that reach the trampoline after the closure was removed
+    /// or without the backup of the current hook.
+    fallback: AtomicPtr<c_void>,
+}
+
+#[allow(clippy::declare_interior_mutable_const)]
+const EMPTY_SLOT: Slot = Slot {
+    used: AtomicBool::new(false),
+    signature: OnceLock::new(),
+    calls: AtomicUsize::new(0),
+    data: AtomicPtr::new(std::ptr::null_mut()),
+    fallback: AtomicPtr::new(std::ptr::null_mut()),
+};
+
+static SLOTS: [Slot; SLOT_COUNT] = [EMPTY_SLOT; SLOT_COUNT];
+
+struct SlotData<F: ClosureFn> {
+    closure: Box<F::Closure>,
+    /// The backup of the [`HookHandle`].
+    backup: Arc<AtomicPtr<c_void>>,
+}
+
+/// A C function pointer type whose hooks can be closures.
+///
+/// This is implemented for `extern "C" fn` pointers with up to 6 arguments. The closure of a
+/// hook with signature `extern "C" fn(A1, A2) -> R` has the type
+/// `dyn Fn(extern "C" fn(A1, A2) -> R, A1, A2) -> R`: it receives the original function
+/// followed by the arguments.
+///
+/// # Safety
+/// Implementors must return a trampoline from [`trampoline`](ClosureFn::trampoline) that
+/// dispatches to the closure stored in the given slot.
+pub unsafe trait ClosureFn: FnPtr + 'static {
+    /// The type of the closure, which takes the original function followed by the arguments.
+    type Closure: ?Sized + Send + Sync + 'static;
+
+    /// The trampoline for `slot`.
+    fn trampoline(slot: usize) -> Self;
+}
+
+/// Runs `call` with the closure of slot `N`, or with `None` if it is being removed, and the
+/// original function.
+///
+/// # Safety
+/// Slot `N` must have been set up by a [`ClosureHook<F>`].
+unsafe fn dispatch<F: ClosureFn, R, const N: usize>(
+    call: impl FnOnce(Option<&F::Closure>, F) -> R,
+) -> R {
+    let slot = &SLOTS[N];
+    slot.calls.fetch_add(1, Ordering::SeqCst);
+    let data = slot.data.load(Ordering::SeqCst) as *const SlotData<F>;
+    let result = if data.is_null() {
+        let original = unsafe { F::from_ptr(slot.fallback.load(Ordering::SeqCst)) };
+        call(None, original)
+    } else {
+        let data = unsafe { &*data };
+        // The backends write the backup before they patch a GOT slot, and `register` rejects GOT
+        // slots that already point to this trampoline, so the backup is only missing for calls
+        // that do not come through this hook. They get the function the GOT slot held when the
+        // hook was registered.
+        let mut original = data.backup.load(Ordering::Acquire);
+        if original.is_null() {
+            original = slot.fallback.load(Ordering::SeqCst);
+        }
+        call(Some(&data.closure), unsafe { F::from_ptr(original) })
+    };
+    slot.calls.fetch_sub(1, Ordering::SeqCst);
+    result
+}
+
+macro_rules! slot_table {
+    ($slot:expr, $thunk:ident::<$($generic:ident),*>) => {
+        match $slot {
+            0 => $thunk::<$($generic,)* 0>,
+            1 => $thunk::<$($generic,)* 1>,
+            2 => $thunk::<$($generic,)* 2>,
+            3 => $thunk::<$($generic,)* 3>,
+            4 => $thunk::<$($generic,)* 4>,
+            5 => $thunk::<$($generic,)* 5>,
+            6 => $thunk::<$($generic,)* 6>,
+            7 => $thunk::<$($generic,)* 7>,
+            8 => $thunk::<$($generic,)* 8>,
+            9 => $thunk::<$($generic,)* 9>,
+            10 => $thunk::<$($generic,)* 10>,
+            11 => $thunk::<$($generic,)* 11>,
+            12 => $thunk::<$($generic,)* 12>,
+            13 => $thunk::<$($generic,)* 13>,
+            14 => $thunk::<$($generic,)* 14>,
+            15 => $thunk::<$($generic,)* 15>,
+            16 => $thunk::<$($generic,)* 16>,
+            17 => $thunk::<$($generic,)* 17>,
+            18 => $thunk::<$($generic,)* 18>,
+            19 => $thunk::<$($generic,)* 19>,
+            20 => $thunk::<$($generic,)* 20>,
+            21 => $thunk::<$($generic,)* 21>,
+            22 => $thunk::<$($generic,)* 22>,
+            23 => $thunk::<$($generic,)* 23>,
+            24 => $thunk::<$($generic,)* 24>,
+            25 => $thunk::<$($generic,)* 25>,
+            26 => $thunk::<$($generic,)* 26>,
+            27 => $thunk::<$($generic,)* 27>,
+            28 => $thunk::<$($generic,)* 28>,
+            29 => $thunk::<$($generic,)* 29>,
+            30 => $thunk::<$($generic,)* 30>,
+            31 => $thunk::<$($generic,)* 31>,
+            _ => unreachable!("closure hook slot out of range"),
+        }
+    };
+}
+
+macro_rules! impl_closure_fn {
+    ($($arg:ident),*) => {
+        unsafe impl<R: 'static, $($arg: 'static),*> ClosureFn for extern "C" fn($($arg),*) -> R {
+            type Closure = dyn Fn(Self, $($arg),*) -> R + Send + Sync;
+
+            fn trampoline(slot: usize) -> Self {
+                #[allow(non_snake_case)]
+                extern "C" fn thunk<R: 'static, $($arg: 'static,)* const N: usize>(
+                    $($arg: $arg),*
+                ) -> R {
+                    // SAFETY: the trampoline of slot `N` is only registered by a `ClosureHook`
+                    // of this signature.
+                    unsafe {
+                        dispatch::<extern "C" fn($($arg),*) -> R, R, N>(|closure, original| {
+                            match closure {
+                                Some(closure) => closure(original, $($arg),*),
+                                None => original($($arg),*),
+                            }
+                        })
+                    }
+                }
+                slot_table!(slot, thunk::<R $(, $arg)*>)
+            }
+        }
+    };
+}
+
+impl_closure_fn!();
+impl_closure_fn!(A1);
+impl_closure_fn!(A1, A2);
+impl_closure_fn!(A1, A2, A3);
+impl_closure_fn!(A1, A2, A3, A4);
+impl_closure_fn!(A1, A2, A3, A4, A5);
+impl_closure_fn!(A1, A2, A3, A4, A5, A6);
+
+/// A hook whose callback is a closure.
+///
+/// Like [`Hook`](crate::Hook), the hook takes effect after [`commit_hook`](crate::commit_hook)
+/// and the original function pointer is restored when the `ClosureHook` is dropped. The closure
+/// receives the original function as its first argument.
+///
+/// # Notes
+/// - At most [`SLOT_COUNT`] closure hooks can exist at the same time. A slot is only reused for
+///   hooks of the same signature. A hook dropped before it was committed keeps its slot until
+///   the commit that applies it has undone it again, and a hook that could not be undone keeps
+///   its slot for good.
+/// - Dropping the hook waits until no call is executing the closure anymore, so it must not be
+///   dropped from inside its own closure.
+/// - A panic in the closure aborts the process, because it cannot unwind through C code.
+///
+/// # Examples
+/// ```no_run
+/// # let (dev, inode) = (0, 0);
+/// use std::sync::atomic::{AtomicUsize, Ordering};
+/// use std::sync::Arc;
+///
+/// type GetPid = extern "C" fn() -> i32;
+///
+/// let calls = Arc::new(AtomicUsize::new(0));
+/// let counter = calls.clone();
+/// let hook = lsplt_rs::ClosureHook::<GetPid>::new(
+///     dev,
+///     inode,
+///     "getpid",
+///     Box::new(move |original: GetPid| {
+///         counter.fetch_add(1, Ordering::Relaxed);
+///         original()
+///     }),
+/// )?;
+/// lsplt_rs::commit_hook()?;
+/// # Ok::<(), lsplt_rs::Error>(())
+/// ```
+pub struct ClosureHook<F: ClosureFn> {
+    slot: usize,
+    handle: Option<HookHandle>,
+    _marker: PhantomData<F>,
+}
+
+impl<F: ClosureFn> ClosureHook<F> {
+    /// Registers `closure` for `symbol` in the library identified by `dev` and `inode`.
+    ///
+    /// See [`register_hook`](crate::register_hook) for details.
+    pub fn new(
+        dev: DeviceId,
+        inode: Inode,
+        symbol: &str,
+        closure: Box<F::Closure>,
+    ) -> Result<Self> {
+        Self::register(closure, |callback| Registration {
+            dev,
+            inode,
+            offset_range: None,
+            symbol: symbol.to_owned(),
+            callback,
+        })
+    }
+
+    /// Registers `closure` for `symbol` in the library at `offset` of the file identified by
+    /// `dev` and `inode`.
+    ///
+    /// See [`register_hook_with_offset`](crate::register_hook_with_offset) for details.
+    pub fn with_offset(
+        dev: DeviceId,
+        inode: Inode,
+        offset: usize,
+        size: usize,
+        symbol: &str,
+        closure: Box<F::Closure>,
+    ) -> Result<Self> {
+        Self::register(closure, |callback| Registration {
+            dev,
+            inode,
+            offset_range: Some((offset, size)),
+            symbol: symbol.to_owned(),
+            callback,
+        })
+    }
+
+    fn register(
+        closure: Box<F::Closure>,
+        registration: impl FnOnce(usize) -> Registration,
+    ) -> Result<Self> {
+        let slot =
+            claim_slot(TypeId::of::<F>()).ok_or(Error::TooManyClosureHooks { max: SLOT_COUNT })?;
+        let registration = registration(F::trampoline(slot).to_ptr() as usize);
+        // The backends do not write the backup of a GOT slot that already holds the callback.
+        let current: Vec<usize> =
+            match registry::resolve_slots(&MapInfo::scan_self(), &registration) {
+                Ok(slots) => slots
+                    .iter()
+                    // SAFETY: the slots lie in the mapped image of the library.
+                    .map(|&slot| unsafe { std::ptr::read_volatile(slot as *const usize) })
+                    .collect(),
+                Err(_) => Vec::new(),
+            };
+        if current.contains(&registration.callback) {
+            SLOTS[slot].used.store(false, Ordering::Release);
+            return Err(Error::InvalidArgument(
+                "the GOT slot already points to this closure hook slot",
+            ));
+        }
+        SLOTS[slot].fallback.store(
+            current
+                .first()
+                .map_or(std::ptr::null_mut(), |&value| value as *mut c_void),
+            Ordering::SeqCst,
+        );
+        // Another thread may commit as soon as the trampoline is registered, so the data must
+        // be in place before.
+        let backup = Arc::<AtomicPtr<c_void>>::default();
+        let data = Box::new(SlotData::<F> {
+            closure,
+            backup: backup.clone(),
+        });
+        SLOTS[slot]
+            .data
+            .store(Box::into_raw(data) as *mut (), Ordering::SeqCst);
+        let handle = match HookHandle::register(registration, backup) {
+            Ok(handle) => handle,
+            Err(err) => {
+                let data = SLOTS[slot]
+                    .data
+                    .swap(std::ptr::null_mut(), Ordering::SeqCst);
+                // SAFETY: the trampoline was never registered, so no call can reach the data.
+                drop(unsafe { Box::from_raw(data as *mut SlotData<F>) });
+                SLOTS[slot].used.store(false, Ordering::Release);
+                return Err(err);
+            }
+        };
+        Ok(ClosureHook {
+            slot,
+            handle: Some(handle),
+            _marker: PhantomData,
+        })
+    }
+
+    /// The original function, or `None` if the hook has not been committed successfully.
+    pub fn original(&self) -> Option<F> {
+        // SAFETY: the backup was read from a GOT slot of the symbol, which the caller declared
+        // to have signature `F` when registering the closure.
+        self.handle()
+            .backup()
+            .map(|ptr| unsafe { F::from_ptr(ptr) })
+    }
+
+    /// The untyped handle of this hook.
+    pub fn handle(&self) -> &HookHandle {
+        self.handle.as_ref().expect("closure hook already released")
+    }
+
+    /// Restores the original function pointer, commits and drops the closure.
+    ///
+    /// See [`HookHandle::unhook`].
+    pub fn unhook(mut self) -> Result<()> {
+        self.release()
+    }
+
+    fn release(&mut self) -> Result<()> {
+        let Some(handle) = self.handle.take() else {
+            return Ok(());
+        };
+        let slot = self.slot;
+        // A hook that was not applied yet is undone by the commit that applies it, and calls may
+        // reach the closure until then, so the slot is only freed afterwards.
+        handle.unhook_then(Box::new(move |undone| free_slot::<F>(slot, undone)))
+    }
+}
+
+/// Drops the closure of slot `index` once its hook is no longer in place, and frees the slot
+/// unless `undone` is `false`, i.e. the GOT may still point to its trampoline.
+///
+/// A slot whose hook could not be undone stays claimed for good, and the calls that still reach
+/// its trampoline go to the original function.
+fn free_slot<F: ClosureFn>(index: usize, undone: bool) {
+    let slot = &SLOTS[index];
+    let data = slot.data.load(Ordering::SeqCst) as *mut SlotData<F>;
+    if !data.is_null() {
+        // SAFETY: `data` was created by `register` and is only freed here.
+        let original = unsafe { &*data }.backup.load(Ordering::Acquire);
+        if !original.is_null() {
+            slot.fallback.store(original, Ordering::SeqCst);
+        }
+        slot.data.store(std::ptr::null_mut(), Ordering::SeqCst);
+        while slot.calls.load(Ordering::SeqCst) != 0 {
+            std::thread::yield_now();
+        }
+        // SAFETY: no call can reach `data` anymore.
+        drop(unsafe { Box::from_raw(data) });
+    }
+    if undone {
+        slot.used.store(false, Ordering::Release);
+    }
+}
+
+/// Claims a free slot for hooks of the signature `signature`.
+fn claim_slot(signature: TypeId) -> Option<usize> {
+    SLOTS.iter().position(|slot| {
+        if slot.signature.get().is_some_and(|&id| id != signature)
+            || slot.calls.load(Ordering::SeqCst) != 0
+            || slot
+                .used
+                .compare_exchange(false, true, Ordering::AcqRel, Ordering::Acquire)
+                .is_err()
+        {
+            return false;
+        }
+        if *slot.signature.get_or_init(|| signature) == signature {
+            return true;
+        }
+        slot.used.store(false, Ordering::Release);
+        false
+    })
+}
+
+impl<F: ClosureFn> Drop for ClosureHook<F> {
+    fn drop(&mut self) {
+        let _ = self.release();
+    }
+}
+
+impl<F: ClosureFn> std::fmt::Debug for ClosureHook<F> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ClosureHook")
+            .field("slot", &self.slot)
+            .field("handle", &self.handle)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+
+    type GetPid = extern "C" fn() -> i32;
+
+    fn hook(symbol: &str, offset: i32) -> Result<ClosureHook<GetPid>> {
+        let module = testing::test_binary();
+        let closure: Box<dyn Fn(GetPid) -> i32 + Send + Sync> =
+            Box::new(move |original| original() + offset);
+        ClosureHook::new(module.dev(), module.inode(), symbol, closure)
+    }
+
+    #[test]
+    fn hooks_calls_through_and_restores() {
+        let _lock = testing::lock();
+        let pid = testing::real_pid();
+
+        let hook = hook("getpid", 1).unwrap();
+        assert!(hook.original().is_none());
+        crate::commit_hook().unwrap();
+        assert_eq!(testing::getpid(), pid + 1);
+        assert_eq!(hook.original().map(|original| original()), Some(pid));
+
+        drop(hook);
+        assert_eq!(testing::getpid(), pid);
+    }
+
+    #[test]
+    fn reuses_released_slots() {
+        let _lock = testing::lock();
+        let pid = testing::real_pid();
+
+        for offset in 0..SLOT_COUNT as i32 + 4 {
+            let hook = hook("getpid", offset).unwrap();
+            crate::commit_hook().unwrap();
+            assert_eq!(testing::getpid(), pid + offset);
+            hook.unhook().unwrap();
+        }
+        // Hooks that never take effect free their slot as well.
+        for _ in 0..SLOT_COUNT + 4 {
+            drop(hook("lsplt_rs_test_missing", 1).unwrap());
+            let _ = crate::commit_hook();
+        }
+        for _ in 0..SLOT_COUNT + 4 {
+            let hook = hook("lsplt_rs_test_missing", 1).unwrap();
+            let _ = crate::commit_hook();
+            drop(hook);
+        }
+        // A hook dropped before its commit is undone by that commit.
+        for _ in 0..SLOT_COUNT + 4 {
+            drop(hook("getpid", 1).unwrap());
+            crate::commit_hook().unwrap();
+            assert_eq!(testing::getpid(), pid);
+        }
+    }
+
+    #[test]
+    fn reports_exhaustion() {
+        let _lock = testing::lock();
+
+        let mut hooks = Vec::new();
+        let err = loop {
+            match hook("lsplt_rs_test_missing", 1) {
+                Ok(hook) => hooks.push(hook),
+                Err(err) => break err,
+            }
+        };
+        assert!(matches!(
+            err,
+            Error::TooManyClosureHooks { max: SLOT_COUNT }
+        ));
+        assert!(!hooks.is_empty() && hooks.len() <= SLOT_COUNT);
+
+        // The slots are freed once the commit shows that the hooks can never be applied.
+        drop(hooks);
+        let _ = crate::commit_hook();
+        let hook = hook("lsplt_rs_test_missing", 1).unwrap();
+        drop(hook);
+        let _ = crate::commit_hook();
+    }
+}
@@ -0,0 +1,329 @@
+use std::path::Path;
+
+use super::*;
+use crate::MapInfo;
+
+/// The dynamic section of an ELF image, with the tables LSPlt uses to find GOT slots.
+///
+/// This is a view of what the hooking engine parses, so it lists the same relocations the
+/// hooks patch. All addresses are virtual addresses of the image as linked. Add
+/// [`load_bias`](DynamicSection::load_bias) to get the address in the current process of a
+/// loaded image.
+///
+/// # Examples
+/// ```no_run
+/// let section = lsplt_rs::elf::DynamicSection::from_file("/system/lib64/libc.so")?;
+/// println!("{:?} needs {:?}", section.soname, section.needed);
+/// for relocation in &section.plt_relocations {
+///     if let Some(symbol) = section.symbol_of(relocation) {
+///         println!("{:#x} -> {}", relocation.offset, symbol.name);
+///     }
+/// }
+/// # Ok::<(), lsplt_rs::Error>(())
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DynamicSection {
+    /// The difference between the addresses in the current process and the virtual addresses
+    /// of the image, or 0 for an image read from a file.
+    pub load_bias: usize,
+    /// The name of the library (`DT_SONAME`).
+    pub soname: Option<String>,
+    /// The libraries the image depends on (`DT_NEEDED`), in load order.
+    pub needed: Vec<String>,
+    /// The address of the dynamic symbol table (`DT_SYMTAB`).
+    pub symtab: usize,
+    /// The address of the dynamic string table (`DT_STRTAB`).
+    pub strtab: usize,
+    /// The size of the dynamic string table in bytes (`DT_STRSZ`).
+    pub strtab_size: usize,
+    /// The GNU hash table (`DT_GNU_HASH`), if there is one.
+    pub gnu_hash: Option<GnuHashTable>,
+    /// The SysV hash table (`DT_HASH`), if there is one.
+    pub sysv_hash: Option<SysvHashTable>,
+    /// The dynamic symbol table. It is empty if the image has no hash table to tell its size.
+    pub symbols: Vec<Symbol>,
+    /// The PLT relocations (`DT_JMPREL`).
+    pub plt_relocations: Vec<Relocation>,
+    /// The other relocations (`DT_RELA` if `DT_PLTREL` says so or `DT_REL` otherwise, like
+    /// LSPlt, and Android's packed `DT_ANDROID_RELA` and `DT_ANDROID_REL`), including the
+    /// `GLOB_DAT` relocations.
+    pub relocations: Vec<Relocation>,
+}
+
+/// The header of a GNU hash table.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GnuHashTable {
+    /// The address of the table.
+    pub address: usize,
+    /// The number of buckets.
+    pub bucket_count: u32,
+    /// The index of the first symbol in the table. Symbols below it, usually the imported ones,
+    /// are not hashed.
+    pub symbol_offset: u32,
+    /// The number of words in the bloom filter.
+    pub bloom_size: u32,
+    /// The shift of the second bloom filter hash.
+    pub bloom_shift: u32,
+}
+
+/// The header of a SysV hash table.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SysvHashTable {
+    /// The address of the table.
+    pub address: usize,
+    /// The number of buckets.
+    pub bucket_count: u32,
+    /// The number of chain entries, which is the number of symbols.
+    pub chain_count: u32,
+}
+
+/// An entry of the dynamic symbol table.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Symbol {
+    /// The name of the symbol, or an empty string for the null symbol.
+    pub name: String,
+    /// The address of the symbol, or 0 if it is imported.
+    pub value: usize,
+    /// The size of the symbol in bytes.
+    pub size: usize,
+    /// The type of the symbol, e.g. 2 for `STT_FUNC`.
+    pub kind: u8,
+    /// The binding of the symbol, e.g. 1 for `STB_GLOBAL`.
+    pub binding: u8,
+    /// The index of the section the symbol is defined in, or 0 (`SHN_UNDEF`) if it is imported.
+    pub section: u16,
+    /// The version of the symbol (`DT_VERSYM`), e.g. `GLIBC_2.14`, or `None` if it has none.
+    pub version: Option<String>,
+}
+
+impl Symbol {
+    /// Whether the symbol is defined in the image, as opposed to imported from another one.
+    pub fn is_defined(&self) -> bool {
+        self.section != 0
+    }
+}
+
+/// A dynamic relocation.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Relocation {
+    /// The address the relocation applies to (`r_offset`).
+    pub offset: usize,
+    /// The architecture specific type of the relocation.
+    pub r_type: u32,
+    /// The kind of GOT slot the relocation fills, or `None` if it is of another type, e.g. a
+    /// relative relocation. Like LSPlt, only the PLT type is recognized in
+    /// [`DynamicSection::plt_relocations`], and only the `GLOB_DAT` and absolute types elsewhere.
+    pub kind: Option<RelocationKind>,
+    /// The index of the referenced symbol in [`DynamicSection::symbols`], or 0 if there is none.
+    pub symbol: usize,
+    /// The explicit addend, for `RELA` relocations.
+    pub addend: Option<isize>,
+}
+
+impl Relocation {
+    fn new(r_offset: usize, r_info: usize, addend: Option<isize>, is_plt: bool) -> Self {
+        let ty = r_type(r_info);
+        Relocation {
+            offset: r_offset,
+            r_type: ty,
+            kind: slot_kind(ty, is_plt),
+            symbol: r_sym(r_info),
+            addend,
+        }
+    }
+}
+
+impl DynamicSection {
+    /// Parses the dynamic section of the image loaded at `base`, e.g. the
+    /// [`start`](MapInfo::start) of the first mapping of a library.
+    ///
+    /// # Errors
+    /// [`Error::InvalidElf`] if `base` does not hold an ELF image of the native class with a
+    /// dynamic section.
+    ///
+    /// # Safety
+    /// `base` must be the start of a readable mapping of an image loaded by the dynamic linker,
+    /// and the image must stay mapped while this function runs.
+    pub unsafe fn from_base(base: usize) -> Result<Self> {
+        Self::from_elf(&Elf::load(base)?)
+    }
+
+    /// Parses the dynamic section of the image mapped by `map`.
+    ///
+    /// # Errors
+    /// [`Error::InvalidArgument`] if `map` is not readable, or [`Error::InvalidElf`] if it does
+    /// not hold an ELF image of the native class with a dynamic section.
+    ///
+    /// # Safety
+    /// `map` must describe a current mapping of the current process, e.g. one returned by
+    /// [`MapInfo::scan_self`], of an image loaded by the dynamic linker.
+    pub unsafe fn from_map(map: &MapInfo) -> Result<Self> {
+        if !map.perms.is_readable() {
+            return Err(Error::InvalidArgument("mapping must be readable"));
+        }
+        Self::from_base(map.start)
+    }
+
+    /// Reads and parses the dynamic section of the ELF file at `path`.
+    ///
+    /// # Errors
+    /// [`Error::Io`] if the file cannot be read, or [`Error::InvalidElf`] if it is not an ELF
+    /// image of the native class with a dynamic section.
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self> {
+        Self::from_elf(&Elf::from_bytes(std::fs::read(path)?)?)
+    }
+
+    /// Parses the dynamic section of the ELF file whose contents are `data`.
+    ///
+    /// # Errors
+    /// [`Error::InvalidElf`] if `data` is not an ELF image of the native class with a dynamic
+    /// section.
+    pub fn from_bytes(data: &[u8]) -> Result<Self> {
+        Self::from_elf(&Elf::from_bytes(data.to_vec())?)
+    }
+
+    /// Finds a symbol by name.
+    ///
+    /// `name` may be of the form `name@VERSION` to find the symbol of that version, e.g.
+    /// `memcpy@GLIBC_2.14`.
+    pub fn symbol(&self, name: &str) -> Option<&Symbol> {
+        let (name, version) = split_version(name);
+        self.symbols.iter().find(|symbol| {
+            symbol.name == name
+                && version.is_none_or(|version| symbol.version.as_deref() == Some(version))
+        })
+    }
+
+    /// The symbol `relocation` refers to, or `None` if it refers to none.
+    pub fn symbol_of(&self, relocation: &Relocation) -> Option<&Symbol> {
+        match relocation.symbol {
+            0 => None,
+            index => self.symbols.get(index),
+        }
+    }
+
+    /// Collects what `elf` parsed.
+    fn from_elf(elf: &Elf) -> Result<Self> {
+        const TRUNCATED: Error = Error::InvalidElf("truncated image");
+
+        if elf.strsz != 0 && elf.bytes(elf.dynstr, elf.strsz).is_none() {
+            return Err(TRUNCATED);
+        }
+        let string = |offset: usize| String::from_utf8_lossy(elf.string(offset)).into_owned();
+
+        let version_names = elf.version_names();
+        let count = elf.symbol_count().ok_or(TRUNCATED)?;
+        let mut symbols = Vec::new();
+        for index in 0..count {
+            let sym = elf.symbol(index).ok_or(TRUNCATED)?;
+            let version = match elf.versions.versym {
+                0 => None,
+                _ => {
+                    let version = elf.version_index(index).ok_or(TRUNCATED)?;
+                    version_names
+                        .iter()
+                        .find(|&&(index, _)| index == version)
+                        .map(|&(_, name)| string(name))
+                }
+            };
+            symbols.push(Symbol {
+                name: string(sym.st_name as usize),
+                value: sym.st_value,
+                size: sym.st_size,
+                kind: sym.st_info & 0xf,
+                binding: sym.st_info >> 4,
+                section: sym.st_shndx,
+                version,
+            });
+        }
+
+        let mut plt_relocations = Vec::new();
+        let mut relocations = Vec::new();
+        elf.for_each_relocation(|r_offset, r_info, addend, is_plt| {
+            let relocation = Relocation::new(r_offset, r_info, addend, is_plt);
+            match is_plt {
+                true => plt_relocations.push(relocation),
+                false => relocations.push(relocation),
+            }
+            false
+        });
+
+        Ok(DynamicSection {
+            load_bias: elf.bias,
+            soname: elf.soname.map(string),
+            needed: elf.needed.iter().copied().map(string).collect(),
+            symtab: elf.dynsym,
+            strtab: elf.dynstr,
+            strtab_size: elf.strsz,
+            gnu_hash: elf.gnu_hash.as_ref().map(|hash| GnuHashTable {
+                address: hash.address,
+                bucket_count: hash.nbucket,
+                symbol_offset: hash.symoffset,
+                bloom_size: hash.bloom_size,
+                bloom_shift: hash.bloom_shift,
+            }),
+            sysv_hash: elf.sysv_hash.as_ref().map(|hash| SysvHashTable {
+                address: hash.address,
+                bucket_count: hash.nbucket,
+                chain_count: hash.nchain,
+            }),
+            symbols,
+            plt_relocations,
+            relocations,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_file_and_memory_alike() {
+        let exe = std::env::current_exe().unwrap();
+        let file = DynamicSection::from_bytes(&std::fs::read(&exe).unwrap()).unwrap();
+        let path = exe.to_str().unwrap();
+        let map = MapInfo::scan_self()
+            .into_iter()
+            .find(|map| map.offset == 0 && map.pathname.as_deref() == Some(path))
+            .unwrap();
+        // SAFETY: the mapping is the first one of the running executable.
+        let memory = unsafe { DynamicSection::from_map(&map) }.unwrap();
+
+        assert!(file.needed.iter().any(|name| name.starts_with("libc.so")));
+        assert_eq!(file.needed, memory.needed);
+        let names = |section: &DynamicSection| -> Vec<String> {
+            section.symbols.iter().map(|sym| sym.name.clone()).collect()
+        };
+        assert_eq!(names(&file), names(&memory));
+        assert_eq!(file.plt_relocations.len(), memory.plt_relocations.len());
+    }
+
+    #[test]
+    fn rejects_malformed_images() {
+        assert!(DynamicSection::from_bytes(b"").is_err());
+        assert!(DynamicSection::from_bytes(b"\x7fELF").is_err());
+
+        let exe = std::fs::read(std::env::current_exe().unwrap()).unwrap();
+        for len in [16, 64, 0x100] {
+            assert!(DynamicSection::from_bytes(&exe[..len]).is_err());
+        }
+        let mut data = exe.clone();
+        data[..4].copy_from_slice(b"\x7fFLE");
+        assert!(DynamicSection::from_bytes(&data).is_err());
+
+        // Corrupting the headers and tables must not make the reader panic.
+        let mut seed = 0x9e37_79b9_u32;
+        for _ in 0..200 {
+            let mut data = exe.clone();
+            for _ in 0..16 {
+                seed ^= seed << 13;
+                seed ^= seed >> 17;
+                seed ^= seed << 5;
+                let index = seed as usize % data.len().min(0x4000);
+                data[index] = (seed >> 24) as u8;
+            }
+            let _ = DynamicSection::from_bytes(&data);
+        }
+    }
+}
@@ -0,0 +1,1213 @@
+//! Readers for the dynamic section of ELF images.
+//!
+//! The reader mirrors what `elf_util.cc` of LSPlt does: it locates the dynamic symbol table and
+//! the relocation tables of a loaded module and finds the GOT slots that reference a given
+//! symbol. It also reads ELF files, with every access bounds checked. [`DynamicSection`] is the
+//! public view of what it parsed, for tooling that wants to see what the hooking engine sees.
+
+use std::ffi::CStr;
+
+use crate::{Error, Result};
+
+mod dynamic;
+
+pub use dynamic::{DynamicSection, GnuHashTable, Relocation, Symbol, SysvHashTable};
+
+pub(crate) const ELF_MAGIC: [u8; 4] = *b"\x7fELF";
+
+#[cfg(target_pointer_width = "64")]
+const ELF_CLASS: u8 = 2;
+#[cfg(target_pointer_width = "32")]
+const ELF_CLASS: u8 = 1;
+
+const PT_LOAD: u32 = 1;
+const PT_DYNAMIC: u32 = 2;
+
+const DT_NULL: isize = 0;
+const DT_NEEDED: isize = 1;
+const DT_PLTRELSZ: isize = 2;
+const DT_HASH: isize = 4;
+const DT_STRTAB: isize = 5;
+const DT_SYMTAB: isize = 6;
+const DT_RELA: isize = 7;
+const DT_RELASZ: isize = 8;
+const DT_STRSZ: isize = 10;
+const DT_SONAME: isize = 14;
+const DT_REL: isize = 17;
+const DT_RELSZ: isize = 18;
+const DT_PLTREL: isize = 20;
+const DT_JMPREL: isize = 23;
+const DT_GNU_HASH: isize = 0x6fff_fef5;
+const DT_ANDROID_REL: isize = 0x6000_000f;
+const DT_ANDROID_RELSZ: isize = 0x6000_0010;
+const DT_ANDROID_RELA: isize = 0x6000_0011;
+const DT_ANDROID_RELASZ: isize = 0x6000_0012;
+const DT_VERSYM: isize = 0x6fff_fff0;
+const DT_VERDEF: isize = 0x6fff_fffc;
+const DT_VERDEFNUM: isize = 0x6fff_fffd;
+const DT_VERNEED: isize = 0x6fff_fffe;
+const DT_VERNEEDNUM: isize = 0x6fff_ffff;
+
+const VER_FLG_BASE: u16 = 1;
+const VERSYM_HIDDEN: u16 = 0x8000;
+
+#[cfg(target_arch = "aarch64")]
+mod reloc {
+    pub const JUMP_SLOT: u32 = 1026;
+    pub const GLOB_DAT: u32 = 1025;
+    pub const ABS: u32 = 257;
+}
+#[cfg(target_arch = "x86_64")]
+mod reloc {
+    pub const JUMP_SLOT: u32 = 7;
+    pub const GLOB_DAT: u32 = 6;
+    pub const ABS: u32 = 1;
+}
+#[cfg(target_arch = "arm")]
+mod reloc {
+    pub const JUMP_SLOT: u32 = 22;
+    pub const GLOB_DAT: u32 = 21;
+    pub const ABS: u32 = 2;
+}
+#[cfg(target_arch = "x86")]
+mod reloc {
+    pub const JUMP_SLOT: u32 = 7;
+    pub const GLOB_DAT: u32 = 6;
+    pub const ABS: u32 = 1;
+}
+#[cfg(target_arch = "riscv64")]
+mod reloc {
+    pub const JUMP_SLOT: u32 = 5;
+    pub const GLOB_DAT: u32 = 2;
+    pub const ABS: u32 = 2;
+}
+
+const PACKED_GROUPED_BY_INFO: usize = 1;
+const PACKED_GROUPED_BY_OFFSET_DELTA: usize = 2;
+const PACKED_GROUPED_BY_ADDEND: usize = 4;
+const PACKED_GROUP_HAS_ADDEND: usize = 8;
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+#[allow(dead_code)]
+struct Ehdr {
+    e_ident: [u8; 16],
+    e_type: u16,
+    e_machine: u16,
+    e_version: u32,
+    e_entry: usize,
+    e_phoff: usize,
+    e_shoff: usize,
+    e_flags: u32,
+    e_ehsize: u16,
+    e_phentsize: u16,
+    e_phnum: u16,
+    e_shentsize: u16,
+    e_shnum: u16,
+    e_shstrndx: u16,
+}
+
+#[cfg(target_pointer_width = "64")]
+#[repr(C)]
+#[derive(Clone, Copy)]
+#[allow(dead_code)]
+struct Phdr {
+    p_type: u32,
+    p_flags: u32,
+    p_offset: usize,
+    p_vaddr: usize,
+    p_paddr: usize,
+    p_filesz: usize,
+    p_memsz: usize,
+    p_align: usize,
+}
+
+#[cfg(target_pointer_width = "32")]
+#[repr(C)]
+#[derive(Clone, Copy)]
+#[allow(dead_code)]
+struct Phdr {
+    p_type: u32,
+    p_offset: usize,
+    p_vaddr: usize,
+    p_paddr: usize,
+    p_filesz: usize,
+    p_memsz: usize,
+    p_flags: u32,
+    p_align: usize,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct Dyn {
+    d_tag: isize,
+    d_val: usize,
+}
+
+#[cfg(target_pointer_width = "64")]
+#[repr(C)]
+#[derive(Clone, Copy)]
+#[allow(dead_code)]
+struct Sym {
+    st_name: u32,
+    st_info: u8,
+    st_other: u8,
+    st_shndx: u16,
+    st_value: usize,
+    st_size: usize,
+}
+
+#[cfg(target_pointer_width = "32")]
+#[repr(C)]
+#[derive(Clone, Copy)]
+#[allow(dead_code)]
+struct Sym {
+    st_name: u32,
+    st_value: usize,
+    st_size: usize,
+    st_info: u8,
+    st_other: u8,
+    st_shndx: u16,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct Rel {
+    r_offset: usize,
+    r_info: usize,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+#[allow(dead_code)]
+struct Rela {
+    r_offset: usize,
+    r_info: usize,
+    r_addend: isize,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+#[allow(dead_code)]
+struct Verneed {
+    vn_version: u16,
+    vn_cnt: u16,
+    vn_file: u32,
+    vn_aux: u32,
+    vn_next: u32,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+#[allow(dead_code)]
+struct Vernaux {
+    vna_hash: u32,
+    vna_flags: u16,
+    vna_other: u16,
+    vna_name: u32,
+    vna_next: u32,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+#[allow(dead_code)]
+struct Verdef {
+    vd_version: u16,
+    vd_flags: u16,
+    vd_ndx: u16,
+    vd_cnt: u16,
+    vd_hash: u32,
+    vd_aux: u32,
+    vd_next: u32,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+#[allow(dead_code)]
+struct Verdaux {
+    vda_name: u32,
+    vda_next: u32,
+}
+
+#[cfg(target_pointer_width = "64")]
+fn r_sym(info: usize) -> usize {
+    info >> 32
+}
+#[cfg(target_pointer_width = "64")]
+fn r_type(info: usize) -> u32 {
+    (info & 0xffff_ffff) as u32
+}
+#[cfg(target_pointer_width = "32")]
+fn r_sym(info: usize) -> usize {
+    info >> 8
+}
+#[cfg(target_pointer_width = "32")]
+fn r_type(info: usize) -> u32 {
+    (info & 0xff) as u32
+}
+
+/// Reads a `T` from an arbitrary address of the current process.
+///
+/// # Safety
+/// `addr` must point to `size_of::<T>()` readable bytes.
+unsafe fn read<T: Copy>(addr: usize) -> T {
+    std::ptr::read_unaligned(addr as *const T)
+}
+
+/// The header of a GNU hash table, with the virtual addresses of its parts.
+struct GnuHash {
+    address: usize,
+    nbucket: u32,
+    symoffset: u32,
+    bloom_size: u32,
+    bloom_shift: u32,
+    bloom: usize,
+    bucket: usize,
+    chain: usize,
+}
+
+/// The header of a SysV hash table, with the virtual addresses of its parts.
+struct SysvHash {
+    address: usize,
+    nbucket: u32,
+    nchain: u32,
+    bucket: usize,
+    chain: usize,
+}
+
+/// The addresses of the symbol versioning tables of an image, or 0 for the missing ones.
+#[derive(Default)]
+struct VersionTables {
+    versym: usize,
+    verneed: usize,
+    verneed_count: usize,
+    verdef: usize,
+    verdef_count: usize,
+}
+
+impl VersionTables {
+    /// Returns `(version index, name)` for every version the image needs (`DT_VERNEED`) or
+    /// defines (`DT_VERDEF`), where `name` is an offset in the dynamic string table.
+    ///
+    /// `bytes` returns the `len` bytes at an address of the tables, or `None` if they are out of
+    /// bounds. `image_size` is the size of the image, which bounds the number of entries.
+    fn names<'a>(
+        &self,
+        image_size: usize,
+        bytes: impl Fn(usize, usize) -> Option<&'a [u8]>,
+    ) -> Vec<(u16, usize)> {
+        let mut names = Vec::new();
+
+        let mut entry = self.verneed;
+        let count = self
+            .verneed_count
+            .min(image_size / std::mem::size_of::<Verneed>());
+        for _ in 0..if entry == 0 { 0 } else { count } {
+            let Some(need) = read_from::<Verneed>(&bytes, entry) else {
+                break;
+            };
+            let mut aux = entry.wrapping_add(need.vn_aux as usize);
+            for _ in 0..need.vn_cnt {
+                let Some(vernaux) = read_from::<Vernaux>(&bytes, aux) else {
+                    break;
+                };
+                names.push((vernaux.vna_other, vernaux.vna_name as usize));
+                if vernaux.vna_next == 0 {
+                    break;
+                }
+                aux = aux.wrapping_add(vernaux.vna_next as usize);
+            }
+            if need.vn_next == 0 {
+                break;
+            }
+            entry = entry.wrapping_add(need.vn_next as usize);
+        }
+
+        let mut entry = self.verdef;
+        let count = self
+            .verdef_count
+            .min(image_size / std::mem::size_of::<Verdef>());
+        for _ in 0..if entry == 0 { 0 } else { count } {
+            let Some(def) = read_from::<Verdef>(&bytes, entry) else {
+                break;
+            };
+            // The base version is the name of the image itself, not a symbol version.
+            if def.vd_flags & VER_FLG_BASE == 0 && def.vd_cnt != 0 {
+                let aux = entry.wrapping_add(def.vd_aux as usize);
+                if let Some(verdaux) = read_from::<Verdaux>(&bytes, aux) {
+                    names.push((def.vd_ndx, verdaux.vda_name as usize));
+                }
+            }
+            if def.vd_next == 0 {
+                break;
+            }
+            entry = entry.wrapping_add(def.vd_next as usize);
+        }
+
+        names
+    }
+}
+
+/// Reads a `T` from the bytes `bytes` returns at `addr`.
+fn read_from<'a, T: Copy>(
+    bytes: &impl Fn(usize, usize) -> Option<&'a [u8]>,
+    addr: usize,
+) -> Option<T> {
+    let data = bytes(addr, std::mem::size_of::<T>())?;
+    // SAFETY: `data` holds `size_of::<T>()` bytes.
+    Some(unsafe { read(data.as_ptr() as usize) })
+}
+
+/// Splits a symbol of the form `name@VERSION` or `name@@VERSION` into its name and version.
+pub(crate) fn split_version(symbol: &str) -> (&str, Option<&str>) {
+    match symbol.split_once('@') {
+        Some((name, version)) => (name, Some(version.trim_start_matches('@'))),
+        None => (symbol, None),
+    }
+}
+
+/// The type of a relocation that makes a GOT slot refer to a symbol.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum RelocationKind {
+    /// A PLT entry (`R_*_JUMP_SLOT`), used for calls to the function.
+    JumpSlot,
+    /// A GOT entry (`R_*_GLOB_DAT`), used when the address of the function is taken.
+    GlobDat,
+    /// An absolute address (`R_*_ABS64`, `R_*_32`, ...), used when the address of the function is
+    /// stored in data.
+    Abs,
+}
+
+/// A relocation that makes a GOT slot refer to a symbol.
+pub(crate) struct SlotRelocation {
+    /// The absolute address of the GOT slot.
+    pub(crate) address: usize,
+    /// The `r_offset` of the relocation entry.
+    pub(crate) r_offset: usize,
+    pub(crate) kind: RelocationKind,
+}
+
+/// The kind of GOT slot a relocation of type `ty` fills. Like LSPlt, only the PLT type is
+/// recognized in the PLT relocations, and only the `GLOB_DAT` and absolute types elsewhere.
+fn slot_kind(ty: u32, is_plt: bool) -> Option<RelocationKind> {
+    if is_plt {
+        (ty == reloc::JUMP_SLOT).then_some(RelocationKind::JumpSlot)
+    } else if ty == reloc::GLOB_DAT {
+        Some(RelocationKind::GlobDat)
+    } else if ty == reloc::ABS {
+        Some(RelocationKind::Abs)
+    } else {
+        None
+    }
+}
+
+/// The address of entry `index` of a table of `size`-byte entries at `start`.
+fn table_entry(start: usize, index: usize, size: usize) -> Option<usize> {
+    index.checked_mul(size)?.checked_add(start)
+}
+
+/// Where the bytes of an image come from.
+enum Image {
+    /// An image loaded into the current process. Like LSPlt, its tables are trusted and read
+    /// without bounds checks.
+    Memory,
+    /// The contents of an ELF file, with its loadable segments. Every read is bounds checked.
+    File { data: Vec<u8>, loads: Vec<Phdr> },
+}
+
+impl Image {
+    /// Reads a `T` at `offset` of the file, which must lie in the first page of the image whose
+    /// ELF header is at `base`.
+    ///
+    /// # Safety
+    /// For a loaded image, `base` must be the start of a readable mapping of it.
+    unsafe fn read_header<T: Copy>(&self, base: usize, offset: usize) -> Option<T> {
+        match self {
+            Image::Memory => Some(read(base.wrapping_add(offset))),
+            Image::File { data, .. } => {
+                let bytes = data.get(offset..offset.checked_add(std::mem::size_of::<T>())?)?;
+                // SAFETY: `bytes` holds `size_of::<T>()` bytes.
+                Some(read(bytes.as_ptr() as usize))
+            }
+        }
+    }
+}
+
+/// The dynamic linking information of an ELF image, either loaded into the current process or
+/// read from a file.
+///
+/// This is the only parser of ELF images in this crate: the hooking engine uses it to find GOT
+/// slots, and [`DynamicSection`] is a view of what it parsed. All addresses are virtual
+/// addresses of the image as linked; add `bias` to get the address in the current process.
+pub(crate) struct Elf {
+    image: Image,
+    /// The address of the ELF header in the current process, or 0 for a file.
+    base: usize,
+    bias: usize,
+    /// The number of bytes spanned by the loadable segments, or the size of the file.
+    size: usize,
+    soname: Option<usize>,
+    needed: Vec<usize>,
+    dynsym: usize,
+    dynstr: usize,
+    strsz: usize,
+    gnu_hash: Option<GnuHash>,
+    sysv_hash: Option<SysvHash>,
+    use_rela: bool,
+    jmprel: usize,
+    jmprel_size: usize,
+    rel: usize,
+    rel_size: usize,
+    rela: usize,
+    rela_size: usize,
+    android_rel: usize,
+    android_rel_size: usize,
+    android_rela: usize,
+    android_rela_size: usize,
+    versions: VersionTables,
+}
+
+impl Elf {
+    /// Parses the ELF image whose header is mapped at `base`.
+    ///
+    /// Returns `None` if `base` does not hold an ELF header of the native class or the image has
+    /// no dynamic section.
+    ///
+    /// # Safety
+    /// `base` must be the start of a readable mapping of a module loaded by the dynamic linker.
+    pub(crate) unsafe fn from_base(base: usize) -> Option<Self> {
+        Self::load(base).ok()
+    }
+
+    /// Like [`from_base`](Elf::from_base), but tells why the image cannot be parsed.
+    ///
+    /// # Safety
+    /// See [`from_base`](Elf::from_base).
+    pub(crate) unsafe fn load(base: usize) -> Result<Self> {
+        Self::parse(Image::Memory, base)
+    }
+
+    /// Parses the ELF file whose contents are `data`.
+    pub(crate) fn from_bytes(data: Vec<u8>) -> Result<Self> {
+        let image = Image::File {
+            data,
+            loads: Vec::new(),
+        };
+        // SAFETY: the headers of a file are read with bounds checks.
+        unsafe { Self::parse(image, 0) }
+    }
+
+    /// # Safety
+    /// For a loaded image, see [`from_base`](Elf::from_base).
+    unsafe fn parse(mut image: Image, base: usize) -> Result<Self> {
+        const NOT_ELF: Error = Error::InvalidElf("not an ELF image of the native class");
+        const NO_DYNAMIC: Error = Error::InvalidElf("no dynamic section");
+        const TRUNCATED: Error = Error::InvalidElf("truncated image");
+
+        let header: Ehdr = image.read_header(base, 0).ok_or(NOT_ELF)?;
+        if header.e_ident[..4] != ELF_MAGIC || header.e_ident[4] != ELF_CLASS {
+            return Err(NOT_ELF);
+        }
+        let mut phdrs = Vec::with_capacity(header.e_phnum as usize);
+        for i in 0..header.e_phnum as usize {
+            let offset =
+                table_entry(header.e_phoff, i, header.e_phentsize as usize).ok_or(TRUNCATED)?;
+            phdrs.push(image.read_header::<Phdr>(base, offset).ok_or(TRUNCATED)?);
+        }
+
+        let dynamic = phdrs
+            .iter()
+            .find(|phdr| phdr.p_type == PT_DYNAMIC)
+            .ok_or(NO_DYNAMIC)?
+            .p_vaddr;
+        let (bias, size) = match &mut image {
+            Image::Memory => {
+                let first = phdrs
+                    .iter()
+                    .find(|phdr| phdr.p_type == PT_LOAD && phdr.p_offset == 0)
+                    .ok_or(NOT_ELF)?;
+                let end = phdrs
+                    .iter()
+                    .filter(|phdr| phdr.p_type == PT_LOAD)
+                    .map(|phdr| phdr.p_vaddr.saturating_add(phdr.p_memsz))
+                    .max()
+                    .unwrap_or(0);
+                (
+                    base.wrapping_sub(first.p_vaddr),
+                    end.saturating_sub(first.p_vaddr),
+                )
+            }
+            Image::File { data, loads } => {
+                loads.extend(phdrs.iter().filter(|phdr| phdr.p_type == PT_LOAD).copied());
+                (0, data.len())
+            }
+        };
+
+        let mut elf = Elf {
+            image,
+            base,
+            bias,
+            size,
+            soname: None,
+            needed: Vec::new(),
+            dynsym: 0,
+            dynstr: 0,
+            strsz: 0,
+            gnu_hash: None,
+            sysv_hash: None,
+            use_rela: false,
+            jmprel: 0,
+            jmprel_size: 0,
+            rel: 0,
+            rel_size: 0,
+            rela: 0,
+            rela_size: 0,
+            android_rel: 0,
+            android_rel_size: 0,
+            android_rela: 0,
+            android_rela_size: 0,
+            versions: VersionTables::default(),
+        };
+
+        let mut hash = 0;
+        let mut gnu_hash = 0;
+        let mut entry = dynamic;
+        loop {
+            let dyn_: Dyn = elf.read(entry).ok_or(TRUNCATED)?;
+            entry = entry
+                .checked_add(std::mem::size_of::<Dyn>())
+                .ok_or(TRUNCATED)?;
+            match dyn_.d_tag {
+                DT_NULL => break,
+                DT_NEEDED => elf.needed.push(dyn_.d_val),
+                DT_SONAME => elf.soname = Some(dyn_.d_val),
+                DT_SYMTAB => elf.dynsym = elf.vaddr(dyn_.d_val),
+                DT_STRTAB => elf.dynstr = elf.vaddr(dyn_.d_val),
+                DT_STRSZ => elf.strsz = dyn_.d_val,
+                DT_HASH => hash = elf.vaddr(dyn_.d_val),
+                DT_GNU_HASH => gnu_hash = elf.vaddr(dyn_.d_val),
+                DT_PLTREL => elf.use_rela = dyn_.d_val as isize == DT_RELA,
+                DT_JMPREL => elf.jmprel = elf.vaddr(dyn_.d_val),
+                DT_PLTRELSZ => elf.jmprel_size = dyn_.d_val,
+                DT_REL => elf.rel = elf.vaddr(dyn_.d_val),
+                DT_RELSZ => elf.rel_size = dyn_.d_val,
+                DT_RELA => elf.rela = elf.vaddr(dyn_.d_val),
+                DT_RELASZ => elf.rela_size = dyn_.d_val,
+                DT_ANDROID_REL => elf.android_rel = elf.vaddr(dyn_.d_val),
+                DT_ANDROID_RELSZ => elf.android_rel_size = dyn_.d_val,
+                DT_ANDROID_RELA => elf.android_rela = elf.vaddr(dyn_.d_val),
+                DT_ANDROID_RELASZ => elf.android_rela_size = dyn_.d_val,
+                DT_VERSYM => elf.versions.versym = elf.vaddr(dyn_.d_val),
+                DT_VERNEED => elf.versions.verneed = elf.vaddr(dyn_.d_val),
+                DT_VERNEEDNUM => elf.versions.verneed_count = dyn_.d_val,
+                DT_VERDEF => elf.versions.verdef = elf.vaddr(dyn_.d_val),
+                DT_VERDEFNUM => elf.versions.verdef_count = dyn_.d_val,
+                _ => {}
+            }
+        }
+
+        // Without its size, the string table of a file could not be bounds checked.
+        let unbounded = matches!(elf.image, Image::File { .. }) && elf.strsz == 0;
+        if elf.dynsym == 0 || elf.dynstr == 0 || unbounded {
+            return Err(NO_DYNAMIC);
+        }
+
+        if gnu_hash != 0 {
+            let words: [u32; 4] = elf.read(gnu_hash).ok_or(TRUNCATED)?;
+            let bloom = gnu_hash.checked_add(16).ok_or(TRUNCATED)?;
+            let bucket = table_entry(bloom, words[2] as usize, std::mem::size_of::<usize>())
+                .ok_or(TRUNCATED)?;
+            elf.gnu_hash = Some(GnuHash {
+                address: gnu_hash,
+                nbucket: words[0],
+                symoffset: words[1],
+                bloom_size: words[2],
+                bloom_shift: words[3],
+                bloom,
+                bucket,
+                chain: table_entry(bucket, words[0] as usize, 4).ok_or(TRUNCATED)?,
+            });
+        }
+        if hash != 0 {
+            let words: [u32; 2] = elf.read(hash).ok_or(TRUNCATED)?;
+            let bucket = hash.checked_add(8).ok_or(TRUNCATED)?;
+            elf.sysv_hash = Some(SysvHash {
+                address: hash,
+                nbucket: words[0],
+                nchain: words[1],
+                bucket,
+                chain: table_entry(bucket, words[0] as usize, 4).ok_or(TRUNCATED)?,
+            });
+        }
+
+        Ok(elf)
+    }
+
+    /// Converts a pointer-valued dynamic entry into a virtual address.
+    ///
+    /// Bionic leaves these entries untouched, while glibc relocates most of them in place on
+    /// architectures whose dynamic section is writable, so values at or above a non-zero load
+    /// bias are treated as relocated.
+    fn vaddr(&self, value: usize) -> usize {
+        if self.bias != 0 && value >= self.bias {
+            value - self.bias
+        } else {
+            value
+        }
+    }
+
+    /// The `len` bytes at the virtual address `vaddr`, or `None` if they are not in the file.
+    fn bytes(&self, vaddr: usize, len: usize) -> Option<&[u8]> {
+        match &self.image {
+            // SAFETY: the tables of a loaded image are mapped while it is loaded.
+            Image::Memory => Some(unsafe {
+                std::slice::from_raw_parts(self.bias.wrapping_add(vaddr) as *const u8, len)
+            }),
+            Image::File { data, loads } => {
+                let end = vaddr.checked_add(len)?;
+                let load = loads.iter().find(|load| {
+                    load.p_vaddr <= vaddr && end <= load.p_vaddr.saturating_add(load.p_filesz)
+                })?;
+                let start = load.p_offset.checked_add(vaddr - load.p_vaddr)?;
+                data.get(start..start.checked_add(len)?)
+            }
+        }
+    }
+
+    /// Reads a `T` at the virtual address `vaddr`.
+    fn read<T: Copy>(&self, vaddr: usize) -> Option<T> {
+        read_from(&|addr, len| self.bytes(addr, len), vaddr)
+    }
+
+    /// Reads entry `index` of the table of `T` at the virtual address `table`.
+    fn entry<T: Copy>(&self, table: usize, index: usize) -> Option<T> {
+        self.read(table_entry(table, index, std::mem::size_of::<T>())?)
+    }
+
+    /// Returns the addresses of every GOT slot that refers to `name`.
+    pub(crate) fn find_plt_addr(&self, name: &str) -> Vec<usize> {
+        self.find_relocations(name)
+            .iter()
+            .map(|relocation| relocation.address)
+            .collect()
+    }
+
+    /// Returns every relocation of a GOT slot that refers to `name`.
+    ///
+    /// `name` may be of the form `name@VERSION` to only match the symbols of that version.
+    pub(crate) fn find_relocations(&self, name: &str) -> Vec<SlotRelocation> {
+        let mut result = Vec::new();
+        let indices = match split_version(name) {
+            (name, Some(version)) => self.lookup_versioned(name.as_bytes(), version.as_bytes()),
+            (name, None) => self.lookup(name.as_bytes()).into_iter().collect(),
+        };
+        if indices.is_empty() {
+            return result;
+        }
+        self.for_each_slot_relocation(|symbol, relocation| {
+            if !indices.contains(&symbol) {
+                return false;
+            }
+            // Like LSPlt, a symbol has a single PLT slot.
+            let is_plt = relocation.kind == RelocationKind::JumpSlot;
+            result.push(relocation);
+            is_plt
+        });
+        result
+    }
+
+    /// Calls `f` with the symbol index and the relocation of every GOT slot the way LSPlt finds
+    /// them, skipping slots that would lie before the ELF header. `f` returns `true` to skip the
+    /// rest of the relocation table.
+    pub(crate) fn for_each_slot_relocation(
+        &self,
+        mut f: impl FnMut(usize, SlotRelocation) -> bool,
+    ) {
+        self.for_each_relocation(|r_offset, r_info, _, is_plt| {
+            let Some(kind) = slot_kind(r_type(r_info), is_plt) else {
+                return false;
+            };
+            let address = self.bias.wrapping_add(r_offset);
+            if address <= self.base {
+                return false;
+            }
+            let relocation = SlotRelocation {
+                address,
+                r_offset,
+                kind,
+            };
+            f(r_sym(r_info), relocation)
+        });
+    }
+
+    /// Calls `f` with `(r_offset, r_info, r_addend, is_plt)` for every relocation LSPlt looks
+    /// at: the PLT relocations, the `DT_RELA` relocations if `DT_PLTREL` says so or the `DT_REL`
+    /// ones otherwise, and the Android packed relocations. `f` returns `true` to skip the rest
+    /// of the relocation table.
+    fn for_each_relocation(&self, mut f: impl FnMut(usize, usize, Option<isize>, bool) -> bool) {
+        let (other, other_size) = if self.use_rela {
+            (self.rela, self.rela_size)
+        } else {
+            (self.rel, self.rel_size)
+        };
+        for (start, size, is_plt) in [
+            (self.jmprel, self.jmprel_size, true),
+            (other, other_size, false),
+        ] {
+            if start == 0 {
+                continue;
+            }
+            if self.use_rela {
+                let count = size / std::mem::size_of::<Rela>();
+                for r in (0..count).map_while(|index| self.entry::<Rela>(start, index)) {
+                    if f(r.r_offset, r.r_info, Some(r.r_addend), is_plt) {
+                        break;
+                    }
+                }
+            } else {
+                let count = size / std::mem::size_of::<Rel>();
+                for r in (0..count).map_while(|index| self.entry::<Rel>(start, index)) {
+                    if f(r.r_offset, r.r_info, None, is_plt) {
+                        break;
+                    }
+                }
+            }
+        }
+        for (start, size, is_rela) in [
+            (self.android_rela, self.android_rela_size, true),
+            (self.android_rel, self.android_rel_size, false),
+        ] {
+            let Some(data) = (start != 0).then(|| self.bytes(start, size)).flatten() else {
+                continue;
+            };
+            decode_packed(data, is_rela, self.size, |r_offset, r_info, r_addend| {
+                f(r_offset, r_info, is_rela.then_some(r_addend), false)
+            });
+        }
+    }
+
+    /// Finds the index of `name` in the dynamic symbol table.
+    fn lookup(&self, name: &[u8]) -> Option<usize> {
+        self.gnu_lookup(name)
+            .or_else(|| self.sysv_lookup(name))
+            .or_else(|| self.linear_lookup(name))
+    }
+
+    /// Finds the indices of every symbol named `name` of the version `version`.
+    ///
+    /// Unlike [`lookup`](Elf::lookup), the whole symbol table is searched, since an image may
+    /// import several versions of the same symbol.
+    fn lookup_versioned(&self, name: &[u8], version: &[u8]) -> Vec<usize> {
+        if self.versions.versym == 0 {
+            return Vec::new();
+        }
+        let indices: Vec<u16> = self
+            .version_names()
+            .into_iter()
+            .filter(|&(_, offset)| self.string(offset) == version)
+            .map(|(index, _)| index)
+            .collect();
+        if indices.is_empty() {
+            return Vec::new();
+        }
+        (1..self.symbol_count().unwrap_or(0))
+            .filter(|&idx| {
+                self.version_index(idx)
+                    .is_some_and(|index| indices.contains(&index))
+                    && self.symbol_name(idx) == Some(name)
+            })
+            .collect()
+    }
+
+    /// Returns `(version index, name)` for every version the image needs or defines, where
+    /// `name` is an offset in the dynamic string table.
+    fn version_names(&self) -> Vec<(u16, usize)> {
+        self.versions
+            .names(self.size, |addr, len| self.bytes(addr, len))
+    }
+
+    /// The version index of symbol `idx`, without the hidden bit, or `None` if the image has no
+    /// versions.
+    fn version_index(&self, idx: usize) -> Option<u16> {
+        if self.versions.versym == 0 {
+            return None;
+        }
+        self.entry::<u16>(self.versions.versym, idx)
+            .map(|index| index & !VERSYM_HIDDEN)
+    }
+
+    /// The number of entries in the dynamic symbol table, as told by the hash tables, or `None`
+    /// if the tables are truncated.
+    fn symbol_count(&self) -> Option<usize> {
+        if let Some(hash) = &self.sysv_hash {
+            return Some(hash.nchain as usize);
+        }
+        let Some(hash) = &self.gnu_hash else {
+            return Some(0);
+        };
+        let mut last = 0;
+        for bucket in 0..hash.nbucket as usize {
+            last = last.max(self.entry::<u32>(hash.bucket, bucket)?);
+        }
+        if last < hash.symoffset {
+            return Some(hash.symoffset as usize);
+        }
+        while self.entry::<u32>(hash.chain, (last - hash.symoffset) as usize)? & 1 == 0 {
+            last = last.checked_add(1)?;
+        }
+        Some(last as usize + 1)
+    }
+
+    /// Entry `idx` of the dynamic symbol table.
+    fn symbol(&self, idx: usize) -> Option<Sym> {
+        self.entry(self.dynsym, idx)
+    }
+
+    pub(crate) fn symbol_name(&self, idx: usize) -> Option<&[u8]> {
+        Some(self.string(self.symbol(idx)?.st_name as usize))
+    }
+
+    /// The string at `offset` in the dynamic string table, or an empty one if it is out of
+    /// bounds.
+    fn string(&self, offset: usize) -> &[u8] {
+        if self.strsz == 0 {
+            // Only loaded images are parsed without the size, and they are trusted.
+            // SAFETY: the string table of a loaded image is mapped while it is loaded.
+            return unsafe {
+                CStr::from_ptr(self.bias.wrapping_add(self.dynstr).wrapping_add(offset) as *const _)
+            }
+            .to_bytes();
+        }
+        if offset >= self.strsz {
+            return &[];
+        }
+        let bytes = self
+            .dynstr
+            .checked_add(offset)
+            .and_then(|start| self.bytes(start, self.strsz - offset))
+            .unwrap_or_default();
+        let end = bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len());
+        &bytes[..end]
+    }
+
+    fn gnu_lookup(&self, name: &[u8]) -> Option<usize> {
+        let hash = self.gnu_hash.as_ref()?;
+        if hash.nbucket == 0 || hash.bloom_size == 0 {
+            return None;
+        }
+        let h = gnu_hash(name);
+        let word_bits = usize::BITS;
+
+        let word: usize = self.entry(hash.bloom, ((h / word_bits) % hash.bloom_size) as usize)?;
+        let mask = (1usize << (h % word_bits)) | (1usize << ((h >> hash.bloom_shift) % word_bits));
+        if word & mask != mask {
+            return None;
+        }
+
+        let mut idx: u32 = self.entry(hash.bucket, (h % hash.nbucket) as usize)?;
+        if idx < hash.symoffset {
+            return None;
+        }
+        loop {
+            let chain_hash: u32 = self.entry(hash.chain, (idx - hash.symoffset) as usize)?;
+            if (h | 1) == (chain_hash | 1) && self.symbol_name(idx as usize) == Some(name) {
+                return Some(idx as usize);
+            }
+            if chain_hash & 1 != 0 {
+                return None;
+            }
+            idx = idx.checked_add(1)?;
+        }
+    }
+
+    fn sysv_lookup(&self, name: &[u8]) -> Option<usize> {
+        let hash = self.sysv_hash.as_ref()?;
+        if hash.nbucket == 0 {
+            return None;
+        }
+        let h = elf_hash(name);
+        let mut idx: u32 = self.entry(hash.bucket, (h % hash.nbucket) as usize)?;
+        // A chain visits every symbol at most once, unless the table is malformed.
+        for _ in 0..hash.nchain {
+            if idx == 0 || idx >= hash.nchain {
+                break;
+            }
+            if self.symbol_name(idx as usize) == Some(name) {
+                return Some(idx as usize);
+            }
+            idx = self.entry(hash.chain, idx as usize)?;
+        }
+        None
+    }
+
+    /// Imported symbols are not part of the GNU hash table; they all live below `symoffset`.
+    fn linear_lookup(&self, name: &[u8]) -> Option<usize> {
+        let count = match (&self.gnu_hash, &self.sysv_hash) {
+            (_, Some(hash)) => hash.nchain,
+            (Some(hash), None) => hash.symoffset,
+            (None, None) => return None,
+        };
+        (1..count as usize)
+            .map_while(|idx| Some((idx, self.symbol_name(idx)?)))
+            .find(|&(_, symbol)| symbol == name)
+            .map(|(idx, _)| idx)
+    }
+}
+
+/// Calls `f` with `(r_offset, r_info, r_addend)` for every relocation of the Android packed
+/// (`APS2`) relocation table `data` until it returns `true`.
+///
+/// A few bytes can declare any number of relocations, so there are never more than one per word
+/// of the `image_size` bytes of the image.
+fn decode_packed(
+    data: &[u8],
+    is_rela: bool,
+    image_size: usize,
+    mut f: impl FnMut(usize, usize, isize) -> bool,
+) {
+    let Some(data) = data.strip_prefix(b"APS2") else {
+        return;
+    };
+    let mut decoder = Sleb128Decoder { data, pos: 0 };
+
+    let Some(num_relocs) = decoder.next() else {
+        return;
+    };
+    let num_relocs = num_relocs.min(image_size / std::mem::size_of::<usize>());
+    let Some(mut r_offset) = decoder.next() else {
+        return;
+    };
+    let mut r_info = 0usize;
+    let mut r_addend = 0isize;
+    let mut index = 0;
+    while index < num_relocs {
+        let (Some(group_size), Some(group_flags)) = (decoder.next(), decoder.next()) else {
+            return;
+        };
+        let group_size = group_size.min(num_relocs - index);
+        let grouped_by_offset = group_flags & PACKED_GROUPED_BY_OFFSET_DELTA != 0;
+        let grouped_by_info = group_flags & PACKED_GROUPED_BY_INFO != 0;
+        let has_addend = group_flags & PACKED_GROUP_HAS_ADDEND != 0;
+        let grouped_by_addend = group_flags & PACKED_GROUPED_BY_ADDEND != 0;
+
+        let mut offset_delta = 0;
+        if grouped_by_offset {
+            let Some(delta) = decoder.next() else { return };
+            offset_delta = delta;
+        }
+        if grouped_by_info {
+            let Some(info) = decoder.next() else { return };
+            r_info = info;
+        }
+        if is_rela && has_addend && grouped_by_addend {
+            let Some(delta) = decoder.next() else { return };
+            r_addend = r_addend.wrapping_add(delta as isize);
+        } else if !has_addend {
+            r_addend = 0;
+        }
+
+        for _ in 0..group_size {
+            if grouped_by_offset {
+                r_offset = r_offset.wrapping_add(offset_delta);
+            } else {
+                let Some(delta) = decoder.next() else { return };
+                r_offset = r_offset.wrapping_add(delta);
+            }
+            if !grouped_by_info {
+                let Some(info) = decoder.next() else { return };
+                r_info = info;
+            }
+            if is_rela && has_addend && !grouped_by_addend {
+                let Some(delta) = decoder.next() else { return };
+                r_addend = r_addend.wrapping_add(delta as isize);
+            }
+            if f(r_offset, r_info, r_addend) {
+                return;
+            }
+        }
+        index += group_size;
+    }
+}
+
+struct Sleb128Decoder<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl Sleb128Decoder<'_> {
+    fn next(&mut self) -> Option<usize> {
+        let mut value = 0usize;
+        let mut shift = 0;
+        loop {
+            let byte = *self.data.get(self.pos)?;
+            self.pos += 1;
+            if shift < usize::BITS {
+                value |= ((byte & 0x7f) as usize) << shift;
+            }
+            shift += 7;
+            if byte & 0x80 == 0 {
+                if shift < usize::BITS && byte & 0x40 != 0 {
+                    value |= usize::MAX << shift;
+                }
+                return Some(value);
+            }
+        }
+    }
+}
+
+fn gnu_hash(name: &[u8]) -> u32 {
+    name.iter()
+        .fold(5381u32, |h, &c| h.wrapping_mul(33).wrapping_add(c as u32))
+}
+
+fn elf_hash(name: &[u8]) -> u32 {
+    let mut h = 0u32;
+    for &c in name {
+        h = (h << 4).wrapping_add(c as u32);
+        let g = h & 0xf000_0000;
+        h ^= g >> 24;
+        h &= !g;
+    }
+    h
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn packed(values: &[isize]) -> Vec<u8> {
+        let mut data = b"APS2".to_vec();
+        for &value in values {
+            let mut value = value as i64;
+            loop {
+                let byte = (value & 0x7f) as u8;
+                value >>= 7;
+                let done = (value == 0 && byte & 0x40 == 0) || (value == -1 && byte & 0x40 != 0);
+                data.push(if done { byte } else { byte | 0x80 });
+                if done {
+                    break;
+                }
+            }
+        }
+        data
+    }
+
+    fn decode(data: &[u8], is_rela: bool, image_size: usize) -> Vec<(usize, usize, isize)> {
+        let mut relocations = Vec::new();
+        decode_packed(data, is_rela, image_size, |offset, info, addend| {
+            relocations.push((offset, info, addend));
+            false
+        });
+        relocations
+    }
+
+    #[test]
+    fn decodes_packed_groups() {
+        let grouped = (PACKED_GROUPED_BY_OFFSET_DELTA | PACKED_GROUPED_BY_INFO) as isize;
+        let with_addend = PACKED_GROUP_HAS_ADDEND as isize;
+        let data = packed(&[
+            3,
+            0x1000, // relocation count and initial offset
+            2,
+            grouped,
+            8,
+            0x403, // two relocations 8 bytes apart with the same info
+            1,
+            with_addend,
+            0x10,
+            0x401,
+            -4, // one relocation with its own delta, info, addend
+        ]);
+        assert_eq!(
+            decode(&data, true, 0x10000),
+            [(0x1008, 0x403, 0), (0x1010, 0x403, 0), (0x1020, 0x401, -4)]
+        );
+        assert!(decode(b"APS1\x01", true, 0x10000).is_empty());
+    }
+
+    #[test]
+    fn bounds_packed_counts() {
+        let grouped = (PACKED_GROUPED_BY_OFFSET_DELTA | PACKED_GROUPED_BY_INFO) as isize;
+        let data = packed(&[isize::MAX, 0, isize::MAX, grouped, 8, 0x403]);
+        assert_eq!(
+            decode(&data, false, 0x100).len(),
+            0x100 / std::mem::size_of::<usize>()
+        );
+        // A truncated table stops at the last complete relocation.
+        let data = packed(&[3, 0, 3, 0, 8, 0x403, 8]);
+        assert_eq!(decode(&data, false, 0x100), [(8, 0x403, 0)]);
+    }
+
+    #[test]
+    fn reads_files_like_loaded_images() {
+        let module = crate::testing::test_binary();
+        // SAFETY: the test binary stays loaded.
+        let memory = unsafe { Elf::load(module.base()) }.unwrap();
+        let file =
+            Elf::from_bytes(std::fs::read(std::env::current_exe().unwrap()).unwrap()).unwrap();
+
+        let offsets = |elf: &Elf| -> Vec<usize> {
+            elf.find_relocations("getpid")
+                .iter()
+                .map(|relocation| relocation.r_offset)
+                .collect()
+        };
+        assert!(!offsets(&memory).is_empty());
+        assert_eq!(offsets(&memory), offsets(&file));
+        let count = memory.symbol_count().unwrap();
+        assert_eq!(file.symbol_count(), Some(count));
+        for index in 0..count {
+            assert_eq!(file.symbol_name(index), memory.symbol_name(index));
+        }
+    }
+
+    #[test]
+    fn splits_versions() {
+        assert_eq!(split_version("memcpy"), ("memcpy", None));
+        assert_eq!(
+            split_version("memcpy@GLIBC_2.14"),
+            ("memcpy", Some("GLIBC_2.14"))
+        );
+        assert_eq!(
+            split_version("memcpy@@GLIBC_2.14"),
+            ("memcpy", Some("GLIBC_2.14"))
+        );
+    }
+
+    #[test]
+    fn finds_versioned_relocations() {
+        let module = crate::testing::test_binary();
+        // SAFETY: the test binary stays loaded.
+        let elf = unsafe { Elf::load(module.base()) }.unwrap();
+        // SAFETY: as above.
+        let section = unsafe { DynamicSection::from_base(module.base()) }.unwrap();
+        let version = section.symbol("getpid").unwrap().version.clone().unwrap();
+
+        let offsets = |name: &str| -> Vec<usize> {
+            elf.find_relocations(name)
+                .iter()
+                .map(|relocation| relocation.r_offset)
+                .collect()
+        };
+        let unversioned = offsets("getpid");
+        assert!(!unversioned.is_empty());
+        assert_eq!(offsets(&format!("getpid@{version}")), unversioned);
+        assert_eq!(offsets(&format!("getpid@@{version}")), unversioned);
+        assert!(offsets("getpid@LSPLT_RS_TEST").is_empty());
+        assert!(offsets(&format!("lsplt_rs_test_missing@{version}")).is_empty());
+        assert_eq!(
+            section.symbol(&format!("getpid@{version}")),
+            section.symbol("getpid")
+        );
+        assert!(section.symbol("getpid@LSPLT_RS_TEST").is_none());
+    }
+
+    #[test]
+    fn hashes_names() {
+        assert_eq!(gnu_hash(b""), 0x1505);
+        assert_eq!(gnu_hash(b"printf"), 0x156b_2bb8);
+        assert_eq!(elf_hash(b""), 0);
+        assert_eq!(elf_hash(b"printf"), 0x0779_05a6);
+    }
+}
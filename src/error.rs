@@ -0,0 +1,183 @@
+use std::fmt;
+
+use crate::{DeviceId, Inode};
+
+/// The error type of this crate.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum Error {
+    /// The symbol contains an interior nul byte and cannot be passed to LSPlt.
+    NulInSymbol(std::ffi::NulError),
+    /// An argument was rejected, e.g. a zero device or inode, an empty symbol or a null callback.
+    InvalidArgument(&'static str),
+    /// The `offset`/`size` pair passed to
+    /// [`register_hook_with_offset`](crate::register_hook_with_offset) is invalid.
+    InvalidOffset {
+        /// The offset to the library in the file.
+        offset: usize,
+        /// The upper bound size to the library in the file.
+        size: usize,
+    },
+    /// The `offset` passed to [`register_hook_with_offset`](crate::register_hook_with_offset) is
+    /// not aligned to the page size.
+    UnalignedOffset {
+        /// The offset to the library in the file.
+        offset: usize,
+        /// The page size of the running system.
+        page_size: usize,
+    },
+    /// The library identified by `dev` and `inode` is not mapped.
+    NotMapped {
+        /// The device number of the library.
+        dev: DeviceId,
+        /// The inode of the library.
+        inode: Inode,
+    },
+    /// No mapped library matches the given name.
+    LibraryNotFound {
+        /// The name or pattern that was looked up.
+        name: String,
+    },
+    /// Several different mapped libraries match the given name.
+    AmbiguousLibrary {
+        /// The name or pattern that was looked up.
+        name: String,
+        /// The paths of the matching libraries.
+        paths: Vec<String>,
+    },
+    /// The library identified by `dev` and `inode` is mapped, but has no valid ELF image at the
+    /// expected offset.
+    ElfNotFound {
+        /// The device number of the library.
+        dev: DeviceId,
+        /// The inode of the library.
+        inode: Inode,
+    },
+    /// The data is not a valid ELF image of the native class, or lacks the dynamic section.
+    InvalidElf(&'static str),
+    /// The library does not reference the symbol through its GOT.
+    SymbolNotFound {
+        /// The symbol that was not found.
+        symbol: String,
+    },
+    /// The file is not a valid zip archive.
+    InvalidArchive(&'static str),
+    /// The archive has no entry with the given name.
+    ArchiveEntryNotFound {
+        /// The name of the entry.
+        name: String,
+    },
+    /// The archive entry cannot be mapped directly, e.g. because it is compressed.
+    UnsupportedArchiveEntry {
+        /// The name of the entry.
+        name: String,
+        /// Why the entry cannot be mapped.
+        reason: &'static str,
+    },
+    /// All slots for closure hooks are in use.
+    TooManyClosureHooks {
+        /// The maximum number of closure hooks that can exist at the same time.
+        max: usize,
+    },
+    /// Remapping or changing the protection of a hooked memory region failed.
+    MemoryProtection(std::io::Error),
+    /// The C++ core threw an exception, which was caught by the C wrapper.
+    CppException,
+    /// An I/O error, e.g. while reading `/proc/self/maps`.
+    Io(std::io::Error),
+}
+
+/// A specialized [`Result`](std::result::Result) type for this crate.
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::NulInSymbol(_) => write!(f, "symbol contains an interior nul byte"),
+            Error::InvalidArgument(reason) => write!(f, "invalid argument: {reason}"),
+            Error::InvalidOffset { offset, size } => {
+                write!(f, "invalid offset range {offset:#x} (size {size:#x})")
+            }
+            Error::UnalignedOffset { offset, page_size } => {
+                write!(
+                    f,
+                    "offset {offset:#x} is not aligned to the page size {page_size:#x}"
+                )
+            }
+            Error::NotMapped { dev, inode } => {
+                write!(f, "no library mapped for device {dev:#x} inode {inode}")
+            }
+            Error::LibraryNotFound { name } => write!(f, "no mapped library matches `{name}`"),
+            Error::AmbiguousLibrary { name, paths } => {
+                write!(
+                    f,
+                    "`{name}` matches several libraries: {}",
+                    paths.join(", ")
+                )
+            }
+            Error::ElfNotFound { dev, inode } => {
+                write!(f, "no ELF image mapped for device {dev:#x} inode {inode}")
+            }
+            Error::InvalidElf(reason) => write!(f, "invalid ELF image: {reason}"),
+            Error::SymbolNotFound { symbol } => write!(f, "symbol `{symbol}` not found"),
+            Error::InvalidArchive(reason) => write!(f, "invalid zip archive: {reason}"),
+            Error::ArchiveEntryNotFound { name } => write!(f, "archive entry `{name}` not found"),
+            Error::UnsupportedArchiveEntry { name, reason } => {
+                write!(f, "archive entry `{name}` cannot be mapped: {reason}")
+            }
+            Error::TooManyClosureHooks { max } => {
+                write!(f, "at most {max} closure hooks can exist at the same time")
+            }
+            Error::MemoryProtection(err) => {
+                write!(f, "failed to remap or protect hooked memory: {err}")
+            }
+            Error::CppException => write!(f, "the C++ core threw an exception"),
+            Error::Io(err) => write!(f, "I/O error: {err}"),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::NulInSymbol(err) => Some(err),
+            Error::MemoryProtection(err) | Error::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::ffi::NulError> for Error {
+    fn from(err: std::ffi::NulError) -> Self {
+        Error::NulInSymbol(err)
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(err: std::io::Error) -> Self {
+        Error::Io(err)
+    }
+}
+
+impl From<Error> for std::io::Error {
+    fn from(err: Error) -> Self {
+        let kind = match &err {
+            Error::NulInSymbol(_)
+            | Error::InvalidArgument(_)
+            | Error::InvalidOffset { .. }
+            | Error::UnalignedOffset { .. }
+            | Error::AmbiguousLibrary { .. }
+            | Error::UnsupportedArchiveEntry { .. } => std::io::ErrorKind::InvalidInput,
+            Error::NotMapped { .. }
+            | Error::LibraryNotFound { .. }
+            | Error::ElfNotFound { .. }
+            | Error::SymbolNotFound { .. }
+            | Error::ArchiveEntryNotFound { .. } => std::io::ErrorKind::NotFound,
+            Error::InvalidElf(_) | Error::InvalidArchive(_) => std::io::ErrorKind::InvalidData,
+            Error::MemoryProtection(err) | Error::Io(err) => err.kind(),
+            Error::TooManyClosureHooks { .. } => std::io::ErrorKind::OutOfMemory,
+            Error::CppException => std::io::ErrorKind::Other,
+        };
+        std::io::Error::new(kind, err)
+    }
+}
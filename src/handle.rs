@@ -0,0 +1,210 @@
+//! Hooks that are undone when their handle is dropped.
+//!
+//! A [`HookHandle`] remembers the target of its registration and owns the backup slot the
+//! backend writes the original function to. Restoring re-registers that original, or, if the
+//! hook is still queued, leaves it to the registry to undo the hook after the commit applies it.
+
+use std::ffi::c_void;
+use std::sync::atomic::{AtomicPtr, Ordering};
+use std::sync::Arc;
+
+use crate::registry::{self, OnRelease, Registration};
+use crate::report::HookOutcome;
+use crate::{DeviceId, Inode};
+
+/// A registered hook that is undone when the handle is dropped.
+///
+/// Obtain one with [`register_hook_scoped`](crate::register_hook_scoped) or
+/// [`register_hook_with_offset_scoped`](crate::register_hook_with_offset_scoped). Like every
+/// registration, the hook only takes effect after [`commit_hook`](crate::commit_hook).
+///
+/// Dropping the handle (or calling [`unhook`](HookHandle::unhook)) re-registers the original
+/// function pointer for the same symbol and commits again. That commit is a full
+/// [`commit_hook`](crate::commit_hook): the backend cannot commit a single registration, so any
+/// other hook registered but not committed yet is applied along with the restore. A handle
+/// dropped before its hook was committed does not commit anything; the hook is undone by the
+/// commit that applies it.
+#[derive(Debug)]
+pub struct HookHandle {
+    dev: DeviceId,
+    inode: Inode,
+    /// `(offset, size)` for hooks registered with an offset range.
+    offset_range: Option<(usize, usize)>,
+    symbol: String,
+    /// Written by the backend on commit, so it must keep a stable address. It is shared with
+    /// the registry if the handle is dropped before the commit.
+    backup: Arc<AtomicPtr<c_void>>,
+    active: bool,
+}
+
+impl HookHandle {
+    /// Hands `registration` to the backend with `backup` as its backup slot.
+    pub(crate) fn register(
+        registration: Registration,
+        backup: Arc<AtomicPtr<c_void>>,
+    ) -> crate::Result<Self> {
+        registry::register(
+            &mut registry::pending(),
+            registration.clone(),
+            backup.as_ptr(),
+        )?;
+        Ok(HookHandle {
+            dev: registration.dev,
+            inode: registration.inode,
+            offset_range: registration.offset_range,
+            symbol: registration.symbol,
+            backup,
+            active: true,
+        })
+    }
+
+    /// The device number of the hooked library.
+    pub fn dev(&self) -> DeviceId {
+        self.dev
+    }
+
+    /// The inode of the hooked library.
+    pub fn inode(&self) -> Inode {
+        self.inode
+    }
+
+    /// The hooked symbol.
+    pub fn symbol(&self) -> &str {
+        &self.symbol
+    }
+
+    /// The original function pointer, or `None` if the hook has not been committed successfully.
+    pub fn backup(&self) -> Option<*mut c_void> {
+        let backup = self.backup.load(Ordering::Acquire);
+        (!backup.is_null()).then_some(backup)
+    }
+
+    /// Restores the original function pointer and commits.
+    ///
+    /// This is what dropping the handle does, except that errors are reported.
+    ///
+    /// # Notes
+    /// - If the hook was registered but not committed yet, nothing is committed. The hook stays
+    ///   queued, since the backend cannot cancel it, and is undone right after the next commit
+    ///   applies it.
+    /// - If the hook never took effect there is nothing to restore and `Ok(())` is returned.
+    /// - Otherwise the commit also applies every other pending registration, like
+    ///   [`commit_hook`](crate::commit_hook) would. Only the outcome of the restore is reported.
+    pub fn unhook(mut self) -> crate::Result<()> {
+        self.restore(None)
+    }
+
+    /// Drops the handle without restoring the hook, e.g. because its library was unloaded.
+    pub(crate) fn discard(mut self) {
+        self.active = false;
+        if self.backup().is_none() {
+            let pending = registry::pending();
+            registry::release_backup(&pending, self.registration(0), self.backup.clone());
+        }
+    }
+
+    fn registration(&self, callback: usize) -> Registration {
+        Registration {
+            dev: self.dev,
+            inode: self.inode,
+            offset_range: self.offset_range,
+            symbol: self.symbol.clone(),
+            callback,
+        }
+    }
+
+    /// Like [`unhook`](HookHandle::unhook), but calls `on_release` once the callback can no
+    /// longer be reached, or with `false` if the original function could not be restored.
+    pub(crate) fn unhook_then(mut self, on_release: OnRelease) -> crate::Result<()> {
+        self.restore(Some(on_release))
+    }
+
+    fn restore(&mut self, on_release: Option<OnRelease>) -> crate::Result<()> {
+        self.active = false;
+
+        let mut pending = registry::pending();
+        let Some(original) = self.backup() else {
+            // The backend writes the backup slot once it applies the hook, so the slot is shared
+            // with the registry.
+            registry::defer_restore(
+                &pending,
+                self.registration(0),
+                self.backup.clone(),
+                on_release,
+            );
+            return Ok(());
+        };
+        let first = pending.len();
+        let result = registry::restore(&mut pending, self.registration(original as usize))
+            .and_then(|()| {
+                let report = registry::commit(&mut pending);
+                let hook = &report.hooks[first];
+                match hook.outcome {
+                    HookOutcome::Patched { .. } => Ok(()),
+                    _ => Err(report.error.unwrap_or_else(|| {
+                        hook.to_error().unwrap_or(crate::Error::NotMapped {
+                            dev: hook.dev,
+                            inode: hook.inode,
+                        })
+                    })),
+                }
+            });
+        drop(pending);
+        if let Some(on_release) = on_release {
+            on_release(result.is_ok());
+        }
+        result
+    }
+}
+
+impl Drop for HookHandle {
+    fn drop(&mut self) {
+        if self.active {
+            let _ = self.restore(None);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::testing;
+
+    extern "C" fn fake_pid() -> i32 {
+        2333
+    }
+
+    extern "C" fn fake_uid() -> u32 {
+        4242
+    }
+
+    #[test]
+    fn restoring_commits_other_pending_hooks() {
+        let _lock = testing::lock();
+        let module = testing::test_binary();
+        let (pid, uid) = (testing::real_pid(), testing::real_uid());
+
+        let handle =
+            crate::register_hook_scoped(module.dev(), module.inode(), "getpid", fake_pid as *mut _)
+                .unwrap();
+        crate::commit_hook().unwrap();
+        assert_eq!(testing::getpid(), 2333);
+
+        let mut backup = std::ptr::null_mut();
+        crate::register_hook(
+            module.dev(),
+            module.inode(),
+            "getuid",
+            fake_uid as *mut _,
+            Some(&mut backup),
+        )
+        .unwrap();
+        assert_eq!(testing::getuid(), uid);
+        handle.unhook().unwrap();
+        assert_eq!(testing::getpid(), pid);
+        assert_eq!(testing::getuid(), 4242);
+
+        crate::register_hook(module.dev(), module.inode(), "getuid", backup, None).unwrap();
+        crate::commit_hook().unwrap();
+        assert_eq!(testing::getuid(), uid);
+    }
+}
@@ -0,0 +1,192 @@
+//! Typed callbacks and hooks.
+//!
+//! [`FnPtr`] is implemented for C function pointers, so that a callback and the original
+//! function can be passed around with their signature rather than as `*mut c_void`.
+//! [`HookTarget`] lets every registration function accept either kind of callback, and
+//! [`Hook`] wraps a [`HookHandle`] to hand out the original function with the same signature.
+
+use std::ffi::c_void;
+use std::marker::PhantomData;
+use std::ptr::NonNull;
+
+use crate::{DeviceId, HookHandle, Inode};
+
+/// A C function pointer type that can be used as a hook callback.
+///
+/// This is implemented for `extern "C" fn` and `unsafe extern "C" fn` pointers with up to 12
+/// arguments.
+///
+/// # Safety
+/// Implementors must be plain function pointers, i.e. have the size of a data pointer and be
+/// callable through the C ABI.
+pub unsafe trait FnPtr: Copy + Send + Sync + 'static {
+    /// Converts the function pointer into an untyped pointer.
+    fn to_ptr(self) -> *mut c_void;
+
+    /// Converts an untyped pointer back into a function pointer.
+    ///
+    /// # Safety
+    /// `ptr` must be non-null and point to a function with this exact signature.
+    unsafe fn from_ptr(ptr: *mut c_void) -> Self;
+}
+
+macro_rules! impl_fn_ptr {
+    ($($arg:ident),*) => {
+        unsafe impl<R: 'static, $($arg: 'static),*> FnPtr for extern "C" fn($($arg),*) -> R {
+            fn to_ptr(self) -> *mut c_void {
+                self as *mut c_void
+            }
+
+            unsafe fn from_ptr(ptr: *mut c_void) -> Self {
+                std::mem::transmute::<*mut c_void, Self>(ptr)
+            }
+        }
+
+        unsafe impl<R: 'static, $($arg: 'static),*> FnPtr for unsafe extern "C" fn($($arg),*) -> R {
+            fn to_ptr(self) -> *mut c_void {
+                self as *mut c_void
+            }
+
+            unsafe fn from_ptr(ptr: *mut c_void) -> Self {
+                std::mem::transmute::<*mut c_void, Self>(ptr)
+            }
+        }
+    };
+}
+
+impl_fn_ptr!();
+impl_fn_ptr!(A1);
+impl_fn_ptr!(A1, A2);
+impl_fn_ptr!(A1, A2, A3);
+impl_fn_ptr!(A1, A2, A3, A4);
+impl_fn_ptr!(A1, A2, A3, A4, A5);
+impl_fn_ptr!(A1, A2, A3, A4, A5, A6);
+impl_fn_ptr!(A1, A2, A3, A4, A5, A6, A7);
+impl_fn_ptr!(A1, A2, A3, A4, A5, A6, A7, A8);
+impl_fn_ptr!(A1, A2, A3, A4, A5, A6, A7, A8, A9);
+impl_fn_ptr!(A1, A2, A3, A4, A5, A6, A7, A8, A9, A10);
+impl_fn_ptr!(A1, A2, A3, A4, A5, A6, A7, A8, A9, A10, A11);
+impl_fn_ptr!(A1, A2, A3, A4, A5, A6, A7, A8, A9, A10, A11, A12);
+
+/// A value that can be passed as the callback of a hook.
+///
+/// This is implemented for every [`FnPtr`], i.e. `extern "C" fn` pointers of any supported
+/// arity, and for untyped pointers such as those returned by `dlsym`, so every registration
+/// function accepts the same callbacks.
+///
+/// # Examples
+/// ```no_run
+/// # let (dev, inode) = (0, 0);
+/// extern "C" fn my_getpid() -> i32 {
+///     2333
+/// }
+///
+/// lsplt_rs::register_hook(dev, inode, "getpid", my_getpid as extern "C" fn() -> i32, None)?;
+/// // SAFETY: the symbol name is nul-terminated.
+/// let getppid = unsafe { libc::dlsym(libc::RTLD_DEFAULT, c"getppid".as_ptr()) };
+/// lsplt_rs::register_hook_with_offset(dev, inode, 0, 0x10000, "getpid", getppid, None)?;
+/// # Ok::<(), lsplt_rs::Error>(())
+/// ```
+pub trait HookTarget {
+    /// Converts the callback into the untyped pointer that is written to the GOT.
+    fn into_callback(self) -> *mut c_void;
+}
+
+impl HookTarget for *mut c_void {
+    fn into_callback(self) -> *mut c_void {
+        self
+    }
+}
+
+impl HookTarget for *const c_void {
+    fn into_callback(self) -> *mut c_void {
+        self.cast_mut()
+    }
+}
+
+impl HookTarget for NonNull<c_void> {
+    fn into_callback(self) -> *mut c_void {
+        self.as_ptr()
+    }
+}
+
+impl<F: FnPtr> HookTarget for F {
+    fn into_callback(self) -> *mut c_void {
+        self.to_ptr()
+    }
+}
+
+/// A typed hook whose callback and original function share the signature `F`.
+///
+/// This is a typed wrapper around [`HookHandle`]: the hook takes effect after
+/// [`commit_hook`](crate::commit_hook), and the original function pointer is restored when the
+/// `Hook` is dropped.
+///
+/// # Examples
+/// ```no_run
+/// # let (dev, inode) = (0, 0);
+/// extern "C" fn my_getpid() -> i32 {
+///     2333
+/// }
+///
+/// let hook = lsplt_rs::Hook::new(dev, inode, "getpid", my_getpid as extern "C" fn() -> i32)?;
+/// lsplt_rs::commit_hook()?;
+/// let original: Option<extern "C" fn() -> i32> = hook.original();
+/// # Ok::<(), lsplt_rs::Error>(())
+/// ```
+#[derive(Debug)]
+pub struct Hook<F: FnPtr> {
+    handle: HookHandle,
+    _marker: PhantomData<F>,
+}
+
+impl<F: FnPtr> Hook<F> {
+    /// Registers `callback` for `symbol` in the library identified by `dev` and `inode`.
+    ///
+    /// See [`register_hook`](crate::register_hook) for details.
+    pub fn new(dev: DeviceId, inode: Inode, symbol: &str, callback: F) -> crate::Result<Self> {
+        Ok(Hook {
+            handle: crate::register_hook_scoped(dev, inode, symbol, callback.to_ptr())?,
+            _marker: PhantomData,
+        })
+    }
+
+    /// Registers `callback` for `symbol` in the library at `offset` of the file identified by
+    /// `dev` and `inode`.
+    ///
+    /// See [`register_hook_with_offset`](crate::register_hook_with_offset) for details.
+    pub fn with_offset(
+        dev: DeviceId,
+        inode: Inode,
+        offset: usize,
+        size: usize,
+        symbol: &str,
+        callback: F,
+    ) -> crate::Result<Self> {
+        Ok(Hook {
+            handle: crate::register_hook_with_offset_scoped(
+                dev, inode, offset, size, symbol, callback,
+            )?,
+            _marker: PhantomData,
+        })
+    }
+
+    /// The original function, or `None` if the hook has not been committed successfully.
+    pub fn original(&self) -> Option<F> {
+        // SAFETY: the backup was read from a GOT slot of `symbol`, which the caller declared to
+        // have signature `F` when registering the callback.
+        self.handle.backup().map(|ptr| unsafe { F::from_ptr(ptr) })
+    }
+
+    /// The untyped handle of this hook.
+    pub fn handle(&self) -> &HookHandle {
+        &self.handle
+    }
+
+    /// Restores the original function pointer and commits.
+    ///
+    /// See [`HookHandle::unhook`].
+    pub fn unhook(self) -> crate::Result<()> {
+        self.handle.unhook()
+    }
+}
@@ -2,126 +2,84 @@
 //!
 //! This module provides a safe Rust interface to the LSPlt hooking functionality,
 //! allowing for function hooking in shared libraries.
+//!
+//! # Features
+//! - `cpp` (default): hooks through the LSPlt C++ core built by `lsplt-sys`.
+//! - `pure-rust`: uses a Rust reimplementation of the LSPlt core instead, so no C++ toolchain or
+//!   Android NDK is required. Build with `default-features = false` to drop `lsplt-sys` entirely.
+//! - `macros`: re-exports the [`plt_hook`] attribute from `lsplt-rs-macros`.
 
-pub type DeviceId = lsplt_sys::dev_t;
-pub type Inode = lsplt_sys::ino_t;
-
-#[derive(Debug, Clone)]
-/// An entry that describes a line in /proc/self/maps. You can obtain a list of these entries
-/// by calling [`scan()`](MapInfo::scan) or [`scan_self()`](MapInfo::scan_self).
-pub struct MapInfo {
-    /// The start address of the memory region.
-    pub start: usize,
-    /// The end address of the memory region.
-    pub end: usize,
-    /// The permissions of the memory region. This is a bit mask of the following values:
-    /// - PROT_READ
-    /// - PROT_WRITE  
-    /// - PROT_EXEC
-    pub perms: u8,
-    /// Whether the memory region is private.
-    pub is_private: bool,
-    /// The offset of the memory region.
-    pub offset: usize,
-    /// The device number of the memory region.
-    /// Major can be obtained by `major()`
-    /// Minor can be obtained by `minor()`
-    pub dev: DeviceId,
-    /// The inode number of the memory region.
-    pub inode: Inode,
-    /// The path of the memory region.
-    pub pathname: Option<String>,
-}
-
-impl MapInfo {
-    fn new(
-        start: usize,
-        end: usize,
-        perms: u8,
-        is_private: bool,
-        offset: usize,
-        dev: DeviceId,
-        inode: Inode,
-        pathname: Option<String>,
-    ) -> Self {
-        MapInfo {
-            start,
-            end,
-            perms,
-            is_private,
-            offset,
-            dev,
-            inode,
-            pathname,
-        }
-    }
-
-    fn from_map_info(mi: &lsplt_sys::lsplt_map_info_t) -> Self {
-        let pathname = if mi.path.is_null() {
-            None
-        } else {
-            unsafe {
-                Some(std::ffi::CStr::from_ptr(mi.path).to_string_lossy().into_owned())
-            }
-        };
-        MapInfo::new(
-            mi.start,
-            mi.end,
-            mi.perms,
-            mi.is_private,
-            mi.offset,
-            mi.dev,
-            mi.inode,
-            pathname,
-        )
-    }
-
-    // Preserve this function for potential future use
-    #[allow(dead_code)]
-    fn to_map_info(&self) -> lsplt_sys::lsplt_map_info_t {
-        lsplt_sys::lsplt_map_info_t {
-            start: self.start,
-            end: self.end,
-            perms: self.perms,
-            is_private: self.is_private,
-            offset: self.offset,
-            dev: self.dev,
-            inode: self.inode,
-            path: match &self.pathname {
-                Some(s) => std::ffi::CString::new(s.as_str()).unwrap().into_raw(),
-                None => std::ptr::null_mut(),
-            },
-        }
-    }
+mod archive;
+mod auto;
+mod backend;
+mod chain;
+mod closure;
+pub mod elf;
+mod error;
+mod handle;
+mod hook;
+mod maps;
+mod module;
+mod plan;
+mod registry;
+mod report;
+#[cfg(test)]
+mod testing;
+mod transaction;
 
-    /// Scans /proc/self/maps and returns a list of [`MapInfo`] entries.
-    /// This is useful to find out the inode of the library to hook.
-    pub fn scan_self() -> Vec<MapInfo> {
-        Self::scan("self")
-    }
+pub use archive::{ArchiveEntry, EmbeddedLibrary};
+pub use auto::{
+    disable_auto_hook, enable_auto_hook, enable_auto_hook_with, register_hook_auto, rehook,
+    AutoHookMode,
+};
+pub use chain::{ChainLink, HookChain};
+pub use closure::{ClosureFn, ClosureHook, SLOT_COUNT};
+pub use elf::RelocationKind;
+pub use error::{Error, Result};
+pub use handle::HookHandle;
+pub use hook::{FnPtr, Hook, HookTarget};
+/// # Examples
+/// ```no_run
+/// use lsplt_rs::plt_hook;
+///
+/// #[plt_hook(library = "libc.so*", symbol = "getpid")]
+/// fn my_getpid() -> i32 {
+///     my_getpid::original().map_or(-1, |getpid| getpid()) + 1
+/// }
+///
+/// fn main() -> lsplt_rs::Result<()> {
+///     // Hooks can also be declared where they are used.
+///     #[plt_hook(library = "libc.so*")]
+///     fn getppid() -> i32 {
+///         1
+///     }
+///
+///     my_getpid::install()?;
+///     getppid::install()?;
+///     assert!(my_getpid::original().is_some());
+///
+///     getppid::uninstall()?;
+///     my_getpid::uninstall()?;
+///     assert!(my_getpid::original().is_none());
+///     Ok(())
+/// }
+/// ```
+#[cfg(feature = "macros")]
+pub use lsplt_rs_macros::plt_hook;
+pub use maps::{MapInfo, ParsePermsError, Perms};
+pub use module::{Import, LoadedModule};
+pub use plan::{HookPlan, PlannedSlot};
+pub use report::{CommitReport, HookOutcome, HookReport, InstalledHook};
+pub use transaction::HookTransaction;
 
-    /// Scans /proc/[pid]/maps and returns a list of [`MapInfo`] entries.
-    /// This is useful to find out the inode of the library to hook.
-    ///
-    /// # Arguments
-    /// * `pid` - The process id to scan. Use "self" for the current process.
-    ///
-    /// # Returns
-    /// A vector of [`MapInfo`] entries describing the memory maps.
-    pub fn scan(pid: &str) -> Vec<MapInfo> {
-        let c_pid = std::ffi::CString::new(pid).unwrap();
-        unsafe {
-            let array = lsplt_sys::lsplt_scan(c_pid.as_ptr());
-            let slice = std::slice::from_raw_parts(array.data, array.size);
-            let mut result = Vec::with_capacity(array.size);
-            for mi in slice {
-                result.push(MapInfo::from_map_info(mi));
-            }
-            lsplt_sys::lsplt_free_map_info_array(&array as *const _ as *mut _);
-            result
-        }
-    }
-}
+#[cfg(not(feature = "pure-rust"))]
+pub type DeviceId = lsplt_sys::dev_t;
+#[cfg(not(feature = "pure-rust"))]
+pub type Inode = lsplt_sys::ino_t;
+#[cfg(feature = "pure-rust")]
+pub type DeviceId = libc::dev_t;
+#[cfg(feature = "pure-rust")]
+pub type Inode = libc::ino_t;
 
 /// Register a hook to a function by inode.
 ///
@@ -131,13 +89,15 @@ impl MapInfo {
 /// # Arguments
 /// * `dev` - The device number of the memory region.
 /// * `inode` - The inode of the library to hook. You can obtain the inode by `stat()` or by finding
-///             the library in the list returned by [`MapInfo::scan`].
-/// * `symbol` - The function symbol to hook.
+///   the library in the list returned by [`MapInfo::scan`].
+/// * `symbol` - The function symbol to hook. Use `name@VERSION`, e.g. `memcpy@GLIBC_2.14`, to
+///   only hook the imports of that version when the library imports several versions of the
+///   symbol. Versioned symbols need the `pure-rust` feature.
 /// * `callback` - The callback function pointer to call when the function is called.
 /// * `backup` - Optional backup function pointer which can call the original function.
 ///
 /// # Returns
-/// `Ok(())` if the hook was successfully registered, or an `io::Error` on failure.
+/// `Ok(())` if the hook was successfully registered, or an [`Error`] on failure.
 ///
 /// # Notes
 /// - This function is thread-safe.
@@ -157,28 +117,23 @@ pub fn register_hook(
     dev: DeviceId,
     inode: Inode,
     symbol: &str,
-    callback: *mut std::ffi::c_void,
+    callback: impl HookTarget,
     backup: Option<&mut *mut std::ffi::c_void>,
-) -> std::io::Result<()> {
-    let c_symbol = std::ffi::CString::new(symbol).unwrap();
-    let result = unsafe {
-        lsplt_sys::lsplt_register_hook(
+) -> Result<()> {
+    registry::register(
+        &mut registry::pending(),
+        registry::Registration {
             dev,
             inode,
-            c_symbol.as_ptr(),
-            callback,
-            match backup {
-                Some(b) => b as *mut *mut std::ffi::c_void,
-                None => std::ptr::null_mut(),
-            },
-        )
-    };
-
-    if result {
-        Ok(())
-    } else {
-        Err(std::io::Error::other("Failed to register hook"))
-    }
+            offset_range: None,
+            symbol: symbol.to_owned(),
+            callback: callback.into_callback() as usize,
+        },
+        match backup {
+            Some(b) => b as *mut *mut std::ffi::c_void,
+            None => std::ptr::null_mut(),
+        },
+    )
 }
 
 /// Register a hook to a function by inode with offset range.
@@ -188,15 +143,18 @@ pub fn register_hook(
 /// # Arguments
 /// * `dev` - The device number of the memory region.
 /// * `inode` - The inode of the library to hook. You can obtain the inode by `stat()` or by finding
-///             the library in the list returned by [`MapInfo::scan`].
+///   the library in the list returned by [`MapInfo::scan`].
 /// * `offset` - The offset to the library in the file.
 /// * `size` - The upper bound size to the library in the file.
-/// * `symbol` - The function symbol to hook.
+/// * `symbol` - The function symbol to hook, optionally with a version as in [`register_hook`].
 /// * `callback` - The callback function to call when the function is called.
 /// * `backup` - Optional backup function pointer which can call the original function.
 ///
 /// # Returns
-/// `Ok(())` if the hook was successfully registered, or an `io::Error` on failure.
+/// `Ok(())` if the hook was successfully registered, or an [`Error`] on failure, e.g.
+/// [`Error::InvalidOffset`] if `offset + size` overflows, [`Error::UnalignedOffset`] if `offset`
+/// is not page aligned, or [`Error::ElfNotFound`] if the file is mapped at `offset` but there is
+/// no ELF header there.
 ///
 /// # Notes
 /// - This function is thread-safe.
@@ -209,14 +167,13 @@ pub fn register_hook(
 ///   so that the original memory region will not be modified. You can invalidate this behavior
 ///   and hook the original memory region by calling [`invalidate_backup`].
 /// - You can get the offset range of the library by getting its entry offset and size in the
-///   zip file.
-/// - According to the Android linker specification, the `offset` must be page aligned.
+///   zip file, e.g. with [`ArchiveEntry::find`].
+/// - According to the Android linker specification, the `offset` must be aligned to the page
+///   size of the running system.
 /// - The `offset` must be accurate, otherwise the hook may fail because the ELF header
-///   cannot be found.
+///   cannot be found. It can only be checked if the file is already mapped at `offset`.
 /// - The `size` can be inaccurate but should be larger or equal to the library size,
 ///   otherwise the hook may fail when the hook pointer is beyond the range.
-/// - The behavior of this function is undefined if `offset + size` is larger than the
-///   maximum value of `usize`.
 ///
 /// # See Also
 /// - [`commit_hook`]
@@ -227,55 +184,397 @@ pub fn register_hook_with_offset(
     offset: usize,
     size: usize,
     symbol: &str,
-    callback: extern "C" fn(),
+    callback: impl HookTarget,
     backup: Option<&mut *mut std::ffi::c_void>,
-) -> std::io::Result<()> {
-    let c_symbol = std::ffi::CString::new(symbol).unwrap();
-    let result = unsafe {
-        lsplt_sys::lsplt_register_hook_with_offset(
+) -> Result<()> {
+    registry::register(
+        &mut registry::pending(),
+        registry::Registration {
             dev,
             inode,
-            offset,
-            size,
-            c_symbol.as_ptr(),
-            callback as usize as *mut std::ffi::c_void,
-            match backup {
-                Some(b) => b as *mut *mut std::ffi::c_void,
-                None => std::ptr::null_mut(),
-            },
-        )
-    };
+            offset_range: Some((offset, size)),
+            symbol: symbol.to_owned(),
+            callback: callback.into_callback() as usize,
+        },
+        match backup {
+            Some(b) => b as *mut *mut std::ffi::c_void,
+            None => std::ptr::null_mut(),
+        },
+    )
+}
+
+/// Register a hook to a function in the library at `path`.
+///
+/// The library is looked up in /proc/self/maps by its path, or by the device and inode that
+/// `stat()` reports for `path`, so symlinks and hard links to it work as well.
+///
+/// # Arguments
+/// * `path` - The path to the library to hook.
+/// * `symbol` - The function symbol to hook.
+/// * `callback` - The callback function pointer to call when the function is called.
+/// * `backup` - Optional backup function pointer which can call the original function.
+///
+/// # Returns
+/// `Ok(())` if the hook was successfully registered, or an [`Error`] on failure, e.g.
+/// [`Error::LibraryNotFound`] if the library is not mapped, or [`Error::AmbiguousLibrary`] if it
+/// is mapped at several offsets.
+///
+/// # Notes
+/// - Unlike [`register_hook`], the library must already be mapped.
+/// - Libraries mapped directly from an archive are registered with their offset range, as if by
+///   [`register_hook_with_offset`].
+///
+/// # See Also
+/// - [`register_hook`]
+/// - [`register_hook_by_name`]
+pub fn register_hook_by_path(
+    path: impl AsRef<std::path::Path>,
+    symbol: &str,
+    callback: impl HookTarget,
+    backup: Option<&mut *mut std::ffi::c_void>,
+) -> Result<()> {
+    use std::os::unix::fs::MetadataExt;
+
+    let path = path.as_ref();
+    let canonical = std::fs::canonicalize(path)?;
+    let metadata = std::fs::metadata(&canonical)?;
+    let module = LoadedModule::find_unique(&path.to_string_lossy(), |module| {
+        std::path::Path::new(module.pathname()) == canonical
+            || (module.dev() == metadata.dev() as DeviceId
+                && module.inode() == metadata.ino() as Inode)
+    })?;
+    register_module_hook(&module, symbol, callback.into_callback(), backup)
+}
+
+/// Register a hook to a function in the loaded library whose file name matches `soname_glob`.
+///
+/// `*` in the pattern matches any run of characters and `?` a single character. The file name
+/// may continue with a version suffix after the match, so `libc.so` also matches `libc.so.6`. If
+/// the pattern contains a `/`, it is matched against the whole path instead of the file name.
+/// This is the rule [`LoadedModule::find_by_name`] uses.
+///
+/// # Arguments
+/// * `soname_glob` - The pattern of the library file name, e.g. `libc.so*`.
+/// * `symbol` - The function symbol to hook.
+/// * `callback` - The callback function pointer to call when the function is called.
+/// * `backup` - Optional backup function pointer which can call the original function.
+///
+/// # Returns
+/// `Ok(())` if the hook was successfully registered, or an [`Error`] on failure, e.g.
+/// [`Error::LibraryNotFound`] if no mapped library matches, or [`Error::AmbiguousLibrary`] with
+/// the matching paths if several different libraries match.
+///
+/// # Notes
+/// - Unlike [`register_hook`], the library must already be mapped.
+/// - A library that is mapped under several paths with the same device and inode counts once.
+///
+/// # See Also
+/// - [`register_hook`]
+/// - [`register_hook_by_path`]
+pub fn register_hook_by_name(
+    soname_glob: &str,
+    symbol: &str,
+    callback: impl HookTarget,
+    backup: Option<&mut *mut std::ffi::c_void>,
+) -> Result<()> {
+    let module = LoadedModule::find_unique(soname_glob, |module| module.matches_glob(soname_glob))?;
+    register_module_hook(&module, symbol, callback.into_callback(), backup)
+}
 
-    if result {
-        Ok(())
-    } else {
-        Err(std::io::Error::other("Failed to register hook with offset"))
+/// Register a hook to a function in every loaded library that references it.
+///
+/// Every loaded ELF module whose GOT references `symbol` and for which `filter` returns `true`
+/// gets its own registration. Anonymous mappings such as the vDSO are never included.
+///
+/// **The hooks only live as long as the returned handles.** Dropping the vector, e.g. by
+/// calling this function as a statement or binding it to `_`, restores every module right
+/// away. Use [`register_hook`] per module for hooks that should stay in place for good.
+///
+/// # Arguments
+/// * `symbol` - The function symbol to hook.
+/// * `callback` - The callback function pointer to call when the function is called.
+/// * `filter` - Decides whether a module is hooked, e.g. to exclude this library with
+///   [`LoadedModule::is_self`] or the dynamic linker with [`LoadedModule::is_linker`].
+///
+/// # Returns
+/// One [`HookHandle`] per registered module, each holding the original function pointer of that
+/// module once committed, or the first [`Error`] on failure.
+///
+/// # Notes
+/// - The hooks still need to be applied with [`commit_hook`].
+/// - Dropping a handle restores the original function pointer of its module, as described
+///   in [`HookHandle`].
+/// - Libraries loaded later are not hooked.
+///
+/// # Examples
+/// ```no_run
+/// # extern "C" fn my_getpid() -> i32 { 2333 }
+/// let hooks = lsplt_rs::register_hook_all("getpid", my_getpid as *mut _, |module| {
+///     !module.is_self() && !module.is_linker()
+/// })?;
+/// lsplt_rs::commit_hook()?;
+/// for (module, handle) in &hooks {
+///     println!("{}: original at {:?}", module.pathname(), handle.backup());
+/// }
+/// # Ok::<(), lsplt_rs::Error>(())
+/// ```
+///
+/// # See Also
+/// - [`register_hook_scoped`]
+/// - [`LoadedModule`]
+#[must_use = "dropping the handles restores every hooked module"]
+pub fn register_hook_all(
+    symbol: &str,
+    callback: impl HookTarget,
+    mut filter: impl FnMut(&LoadedModule) -> bool,
+) -> Result<Vec<(LoadedModule, HookHandle)>> {
+    let callback = callback.into_callback();
+    let mut hooks: Vec<(LoadedModule, HookHandle)> = Vec::new();
+    for module in LoadedModule::scan_self() {
+        if hooks
+            .iter()
+            .any(|(found, _)| found.is_same_library(&module))
+            || !module.references_symbol(symbol)
+            || !filter(&module)
+        {
+            continue;
+        }
+        let handle = register_module_hook_scoped(&module, symbol, callback)?;
+        hooks.push((module, handle));
     }
+    Ok(hooks)
+}
+
+fn register_module_hook_scoped(
+    module: &LoadedModule,
+    symbol: &str,
+    callback: *mut std::ffi::c_void,
+) -> Result<HookHandle> {
+    HookHandle::register(
+        registry::Registration {
+            dev: module.dev(),
+            inode: module.inode(),
+            offset_range: module.offset_range(),
+            symbol: symbol.to_owned(),
+            callback: callback as usize,
+        },
+        Default::default(),
+    )
+}
+
+fn register_module_hook(
+    module: &LoadedModule,
+    symbol: &str,
+    callback: *mut std::ffi::c_void,
+    backup: Option<&mut *mut std::ffi::c_void>,
+) -> Result<()> {
+    registry::register(
+        &mut registry::pending(),
+        registry::Registration {
+            dev: module.dev(),
+            inode: module.inode(),
+            offset_range: module.offset_range(),
+            symbol: symbol.to_owned(),
+            callback: callback as usize,
+        },
+        match backup {
+            Some(b) => b as *mut *mut std::ffi::c_void,
+            None => std::ptr::null_mut(),
+        },
+    )
+}
+
+/// Register a hook to a function by inode and return a [`HookHandle`] that undoes it on drop.
+///
+/// This behaves like [`register_hook`], except that the backup is kept by the returned handle.
+/// The hook still needs to be applied with [`commit_hook`]. Dropping the handle, or calling
+/// [`HookHandle::unhook`], re-registers the original function pointer and commits again.
+///
+/// # Arguments
+/// * `dev` - The device number of the memory region.
+/// * `inode` - The inode of the library to hook.
+/// * `symbol` - The function symbol to hook.
+/// * `callback` - The callback function pointer to call when the function is called.
+///
+/// # Returns
+/// The [`HookHandle`] if the hook was successfully registered, or an [`Error`] on failure.
+///
+/// # See Also
+/// - [`register_hook`]
+/// - [`commit_hook`]
+pub fn register_hook_scoped(
+    dev: DeviceId,
+    inode: Inode,
+    symbol: &str,
+    callback: impl HookTarget,
+) -> Result<HookHandle> {
+    HookHandle::register(
+        registry::Registration {
+            dev,
+            inode,
+            offset_range: None,
+            symbol: symbol.to_owned(),
+            callback: callback.into_callback() as usize,
+        },
+        Default::default(),
+    )
+}
+
+/// Register a hook to a function by inode with offset range and return a [`HookHandle`] that
+/// undoes it on drop.
+///
+/// This behaves like [`register_hook_with_offset`], except that the backup is kept by the
+/// returned handle. The hook still needs to be applied with [`commit_hook`].
+///
+/// # Arguments
+/// * `dev` - The device number of the memory region.
+/// * `inode` - The inode of the library to hook.
+/// * `offset` - The offset to the library in the file.
+/// * `size` - The upper bound size to the library in the file.
+/// * `symbol` - The function symbol to hook.
+/// * `callback` - The callback function to call when the function is called.
+///
+/// # Returns
+/// The [`HookHandle`] if the hook was successfully registered, or an [`Error`] on failure.
+///
+/// # See Also
+/// - [`register_hook_with_offset`]
+/// - [`commit_hook`]
+pub fn register_hook_with_offset_scoped(
+    dev: DeviceId,
+    inode: Inode,
+    offset: usize,
+    size: usize,
+    symbol: &str,
+    callback: impl HookTarget,
+) -> Result<HookHandle> {
+    HookHandle::register(
+        registry::Registration {
+            dev,
+            inode,
+            offset_range: Some((offset, size)),
+            symbol: symbol.to_owned(),
+            callback: callback.into_callback() as usize,
+        },
+        Default::default(),
+    )
+}
+
+/// Resolve what a hook to a function by inode would patch, without registering it.
+///
+/// This is a dry run of [`register_hook`] followed by [`commit_hook`]: the library and symbol are
+/// resolved the same way, but no memory is made writable or written to.
+///
+/// # Arguments
+/// * `dev` - The device number of the memory region.
+/// * `inode` - The inode of the library to hook.
+/// * `symbol` - The function symbol to hook.
+///
+/// # Returns
+/// A [`HookPlan`] with every GOT slot that would be patched, its relocation entry and its
+/// current value, or an [`Error`] if the hook would fail, e.g. [`Error::NotMapped`] if the
+/// library is not mapped, or [`Error::SymbolNotFound`] if it does not reference `symbol`.
+///
+/// # Notes
+/// - This function is thread-safe.
+/// - Unlike [`register_hook`], the library must already be mapped.
+/// - The slots are resolved from the mapped ELF image. With the C++ backend, LSPlt resolves them
+///   on its own when committing and is expected to find the same ones.
+///
+/// # See Also
+/// - [`register_hook`]
+/// - [`plan_hook_with_offset`]
+pub fn plan_hook(dev: DeviceId, inode: Inode, symbol: &str) -> Result<HookPlan> {
+    registry::plan(&registry::Registration {
+        dev,
+        inode,
+        offset_range: None,
+        symbol: symbol.to_owned(),
+        callback: 0,
+    })
+}
+
+/// Resolve what a hook to a function in a library within an archive would patch, without
+/// registering it.
+///
+/// See [`plan_hook`] and [`register_hook_with_offset`] for details.
+pub fn plan_hook_with_offset(
+    dev: DeviceId,
+    inode: Inode,
+    offset: usize,
+    size: usize,
+    symbol: &str,
+) -> Result<HookPlan> {
+    registry::plan(&registry::Registration {
+        dev,
+        inode,
+        offset_range: Some((offset, size)),
+        symbol: symbol.to_owned(),
+        callback: 0,
+    })
 }
 
 /// Commit all registered hooks.
 ///
 /// # Returns
-/// `Ok(())` if all hooks were successfully committed, or an `io::Error` if any hook failed.
+/// `Ok(())` if the backend committed all hooks, or an [`Error`] if it failed.
 ///
 /// # Notes
 /// - This function is thread-safe.
-/// - The return value indicates whether all hooks are successfully committed. You can
-///   determine which hook fails by checking the backup function pointer of [`register_hook`].
+/// - The return value indicates whether the backend succeeded, like LSPlt's `CommitHook`. A
+///   registration whose library does not reference the symbol is not an error. Use
+///   [`commit_hook_with_report`] to find out what happened to each registration.
+/// - Hooks of libraries that are not mapped yet stay pending and are applied by a later commit.
 ///
 /// # See Also
 /// - [`register_hook`]
 /// - [`register_hook_with_offset`]
-pub fn commit_hook() -> std::io::Result<()> {
-    if unsafe {
-        lsplt_sys::lsplt_commit_hook()
-    } {
-        Ok(())
-    } else {
-        Err(std::io::Error::other("Failed to commit hook"))
+/// - [`commit_hook_with_report`]
+pub fn commit_hook() -> Result<()> {
+    match commit_hook_with_report().error {
+        Some(err) => Err(err),
+        None => Ok(()),
     }
 }
 
+/// Commit all registered hooks and report the outcome of every registration.
+///
+/// # Returns
+/// A [`CommitReport`] with one [`HookReport`] per registration that was pending, including the
+/// number of GOT slots that were patched for it.
+///
+/// # Notes
+/// - This function is thread-safe.
+/// - The outcome is determined by resolving the GOT slots of each registration before the
+///   commit and checking whether they point to the callback afterwards. When several
+///   registrations hook the same slot, the last one wins and the earlier ones are still reported
+///   as patched.
+///
+/// # See Also
+/// - [`commit_hook`]
+pub fn commit_hook_with_report() -> CommitReport {
+    registry::commit(&mut registry::pending())
+}
+
+/// List the hooks that are currently in place.
+///
+/// # Returns
+/// One [`InstalledHook`] per hooked symbol and library, with the callback, the patched GOT slots
+/// and their original values.
+///
+/// # Notes
+/// - This function is thread-safe.
+/// - The list is updated on every commit: hooks are added once their GOT slots are patched and
+///   removed once the original function is restored or their library is no longer mapped.
+/// - Hooks that were replaced by another registration for the same symbol show the latest
+///   callback and the original values from before the first hook.
+///
+/// # See Also
+/// - [`commit_hook_with_report`]
+pub fn installed_hooks() -> Vec<InstalledHook> {
+    registry::installed().clone()
+}
+
 /// Invalidate backup memory regions.
 ///
 /// Normally LSPlt will backup the hooked memory region and do hook on a copied anonymous memory
@@ -284,7 +583,7 @@ pub fn commit_hook() -> std::io::Result<()> {
 /// the backup memory region and do all existing hooks on the original memory region.
 ///
 /// # Returns
-/// `Ok(())` if all hooks were successfully invalidated, or an `io::Error` if any hook failed.
+/// `Ok(())` if all hooks were successfully invalidated, or the first [`Error`] if any hook failed.
 ///
 /// # Notes
 /// - This function is thread-safe.
@@ -292,12 +591,6 @@ pub fn commit_hook() -> std::io::Result<()> {
 ///
 /// # See Also
 /// - [`register_hook`]
-pub fn invalidate_backup() -> std::io::Result<()> {
-    if unsafe {
-        lsplt_sys::lsplt_invalidate_backup()
-    } {
-        Ok(())
-    } else {
-        Err(std::io::Error::other("Failed to invalidate backup"))
-    }
-}
\ No newline at end of file
+pub fn invalidate_backup() -> Result<()> {
+    backend::invalidate_backup()
+}
@@ -0,0 +1,450 @@
+//! Parser for `/proc/[pid]/maps`.
+
+use std::fmt;
+use std::io::{self, BufRead, BufReader};
+use std::str::FromStr;
+
+use crate::archive::{self, EmbeddedLibrary};
+use crate::{DeviceId, Inode};
+
+/// The suffix the kernel appends to the path of a mapped file that has been unlinked.
+const DELETED_SUFFIX: &str = " (deleted)";
+
+bitflags::bitflags! {
+    /// The permissions of a memory region, as shown in the second column of /proc/[pid]/maps.
+    ///
+    /// [`READ`](Perms::READ), [`WRITE`](Perms::WRITE) and [`EXEC`](Perms::EXEC) have the values
+    /// of the corresponding `PROT_*` constants.
+    ///
+    /// # Examples
+    /// ```
+    /// use lsplt_rs::Perms;
+    ///
+    /// let perms: Perms = "r-xp".parse()?;
+    /// assert!(perms.is_executable() && !perms.is_writable());
+    /// assert_eq!(perms.to_string(), "r-xp");
+    /// # Ok::<(), lsplt_rs::ParsePermsError>(())
+    /// ```
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+    pub struct Perms: u8 {
+        /// The region is readable (`PROT_READ`).
+        const READ = libc::PROT_READ as u8;
+        /// The region is writable (`PROT_WRITE`).
+        const WRITE = libc::PROT_WRITE as u8;
+        /// The region is executable (`PROT_EXEC`).
+        const EXEC = libc::PROT_EXEC as u8;
+        /// The region is a private copy-on-write mapping rather than a shared one.
+        const PRIVATE = 0x80;
+    }
+}
+
+impl Perms {
+    /// Creates permissions from a `PROT_*` bit mask and the private flag.
+    pub fn from_prot(prot: u8, is_private: bool) -> Self {
+        let mut perms = Perms::from_bits_truncate(prot) & !Perms::PRIVATE;
+        perms.set(Perms::PRIVATE, is_private);
+        perms
+    }
+
+    /// The `PROT_*` bit mask, e.g. for `mprotect()`.
+    pub fn prot(self) -> i32 {
+        (self & !Perms::PRIVATE).bits() as i32
+    }
+
+    /// Whether the region is readable.
+    pub fn is_readable(self) -> bool {
+        self.contains(Perms::READ)
+    }
+
+    /// Whether the region is writable.
+    pub fn is_writable(self) -> bool {
+        self.contains(Perms::WRITE)
+    }
+
+    /// Whether the region is executable.
+    pub fn is_executable(self) -> bool {
+        self.contains(Perms::EXEC)
+    }
+
+    /// Whether the region is private rather than shared.
+    pub fn is_private(self) -> bool {
+        self.contains(Perms::PRIVATE)
+    }
+}
+
+impl fmt::Display for Perms {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let flag = |set, c| if set { c } else { '-' };
+        write!(
+            f,
+            "{}{}{}{}",
+            flag(self.is_readable(), 'r'),
+            flag(self.is_writable(), 'w'),
+            flag(self.is_executable(), 'x'),
+            if self.is_private() { 'p' } else { 's' },
+        )
+    }
+}
+
+/// The error returned when parsing [`Perms`] from a string fails.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParsePermsError(());
+
+impl fmt::Display for ParsePermsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid permissions, expected e.g. `r-xp`")
+    }
+}
+
+impl std::error::Error for ParsePermsError {}
+
+impl FromStr for Perms {
+    type Err = ParsePermsError;
+
+    /// Parses permissions in the format of /proc/[pid]/maps, e.g. `r-xp` or `rw-s`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let [read, write, exec, shared] = *s.as_bytes() else {
+            return Err(ParsePermsError(()));
+        };
+        let mut perms = Perms::empty();
+        for (c, expected, flag) in [
+            (read, b'r', Perms::READ),
+            (write, b'w', Perms::WRITE),
+            (exec, b'x', Perms::EXEC),
+        ] {
+            match c {
+                b'-' => {}
+                c if c == expected => perms |= flag,
+                _ => return Err(ParsePermsError(())),
+            }
+        }
+        match shared {
+            b'p' => perms |= Perms::PRIVATE,
+            b's' => {}
+            _ => return Err(ParsePermsError(())),
+        }
+        Ok(perms)
+    }
+}
+
+#[derive(Debug, Clone)]
+/// An entry that describes a line in /proc/self/maps. You can obtain a list of these entries
+/// by calling [`scan()`](MapInfo::scan) or [`scan_self()`](MapInfo::scan_self).
+pub struct MapInfo {
+    /// The start address of the memory region.
+    pub start: usize,
+    /// The end address of the memory region.
+    pub end: usize,
+    /// The permissions of the memory region, including whether it is private.
+    pub perms: Perms,
+    /// The offset of the memory region.
+    pub offset: usize,
+    /// The device number of the memory region.
+    /// Major can be obtained by `major()`
+    /// Minor can be obtained by `minor()`
+    pub dev: DeviceId,
+    /// The inode number of the memory region.
+    pub inode: Inode,
+    /// The path of the memory region, without the ` (deleted)` suffix.
+    pub pathname: Option<String>,
+    /// Whether the mapped file has been deleted, i.e. its path had the ` (deleted)` suffix.
+    pub deleted: bool,
+}
+
+impl MapInfo {
+    /// Creates an entry, stripping the ` (deleted)` suffix from `pathname`.
+    pub(crate) fn new(
+        start: usize,
+        end: usize,
+        perms: Perms,
+        offset: usize,
+        dev: DeviceId,
+        inode: Inode,
+        mut pathname: Option<String>,
+    ) -> Self {
+        let deleted = match &mut pathname {
+            Some(path) if inode != 0 && path.ends_with(DELETED_SUFFIX) => {
+                path.truncate(path.len() - DELETED_SUFFIX.len());
+                true
+            }
+            _ => false,
+        };
+        MapInfo {
+            start,
+            end,
+            perms,
+            offset,
+            dev,
+            inode,
+            pathname,
+            deleted,
+        }
+    }
+
+    /// Whether the memory region is private rather than shared.
+    #[deprecated(note = "use `perms.is_private()` instead")]
+    pub fn is_private(&self) -> bool {
+        self.perms.is_private()
+    }
+
+    /// Finds the library this region maps if the mapped file is a zip archive, e.g. an APK
+    /// whose uncompressed library Android loaded directly.
+    ///
+    /// The archive is opened by its path and its central directory is read to find the entry
+    /// that contains [`offset`](MapInfo::offset), which must hold an ELF image.
+    ///
+    /// # Returns
+    /// The entry name, ELF offset and size of the library, `None` if the region does not map a
+    /// library inside an archive, or an [`Error`](crate::Error) if the archive cannot be read.
+    ///
+    /// # Examples
+    /// ```no_run
+    /// for map in lsplt_rs::MapInfo::scan_self() {
+    ///     if let Some(library) = map.embedded_library()? {
+    ///         println!("{:?}!/{} at {:#x}", map.pathname, library.name, library.offset);
+    ///     }
+    /// }
+    /// # Ok::<(), lsplt_rs::Error>(())
+    /// ```
+    pub fn embedded_library(&self) -> crate::Result<Option<EmbeddedLibrary>> {
+        match &self.pathname {
+            Some(path) if self.inode != 0 && self.offset != 0 && !self.deleted => {
+                archive::find_embedded_library(path, self.dev, self.inode, self.offset)
+            }
+            _ => Ok(None),
+        }
+    }
+
+    /// Scans /proc/self/maps and returns a list of [`MapInfo`] entries.
+    /// This is useful to find out the inode of the library to hook.
+    pub fn scan_self() -> Vec<MapInfo> {
+        Self::scan("self")
+    }
+
+    /// Scans /proc/[pid]/maps and returns a list of [`MapInfo`] entries.
+    /// This is useful to find out the inode of the library to hook.
+    ///
+    /// # Arguments
+    /// * `pid` - The process id to scan. Use "self" for the current process.
+    ///
+    /// # Returns
+    /// A vector of [`MapInfo`] entries describing the memory maps, or an empty vector if the
+    /// maps cannot be read. Use [`try_scan`](MapInfo::try_scan) to get the error instead.
+    pub fn scan(pid: &str) -> Vec<MapInfo> {
+        Self::try_scan(pid).unwrap_or_default()
+    }
+
+    /// Scans /proc/[pid]/maps and returns a list of [`MapInfo`] entries.
+    ///
+    /// # Arguments
+    /// * `pid` - The process id to scan. Use "self" for the current process.
+    ///
+    /// # Returns
+    /// The entries describing the memory maps, or the error that occurred while opening or
+    /// parsing the maps file.
+    pub fn try_scan(pid: &str) -> io::Result<Vec<MapInfo>> {
+        let file = std::fs::File::open(format!("/proc/{pid}/maps"))?;
+        Self::parse(BufReader::new(file))
+    }
+
+    /// Parses entries in the format of /proc/[pid]/maps from `reader`.
+    ///
+    /// Paths may contain spaces. Bytes that are not valid UTF-8 are replaced with
+    /// `U+FFFD REPLACEMENT CHARACTER`.
+    ///
+    /// # Returns
+    /// The parsed entries, or an [`io::Error`] of kind [`InvalidData`](io::ErrorKind::InvalidData)
+    /// if a line is malformed.
+    ///
+    /// # Examples
+    /// ```
+    /// let maps = "7f0000000000-7f0000001000 r-xp 00001000 fd:01 1234   /tmp/lib foo.so (deleted)\n";
+    /// let maps = lsplt_rs::MapInfo::parse(maps.as_bytes())?;
+    /// assert_eq!(maps[0].pathname.as_deref(), Some("/tmp/lib foo.so"));
+    /// assert!(maps[0].deleted);
+    /// # Ok::<(), std::io::Error>(())
+    /// ```
+    pub fn parse<R: BufRead>(mut reader: R) -> io::Result<Vec<MapInfo>> {
+        let mut maps = Vec::new();
+        let mut buf = Vec::new();
+        loop {
+            buf.clear();
+            if reader.read_until(b'\n', &mut buf)? == 0 {
+                return Ok(maps);
+            }
+            let line = String::from_utf8_lossy(&buf);
+            let line = line.trim_end_matches('\n');
+            if line.is_empty() {
+                continue;
+            }
+            let map = parse_line(line).ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("malformed maps entry: {line}"),
+                )
+            })?;
+            maps.push(map);
+        }
+    }
+}
+
+#[cfg(feature = "cpp")]
+impl From<&lsplt_sys::lsplt_map_info_t> for MapInfo {
+    /// Converts an entry returned by `lsplt_scan()`, copying its path.
+    fn from(mi: &lsplt_sys::lsplt_map_info_t) -> Self {
+        let pathname = if mi.path.is_null() {
+            None
+        } else {
+            // SAFETY: LSPlt sets `path` to null or to a nul-terminated string.
+            let path = unsafe { std::ffi::CStr::from_ptr(mi.path) };
+            Some(path.to_string_lossy().into_owned())
+        };
+        MapInfo::new(
+            mi.start,
+            mi.end,
+            Perms::from_prot(mi.perms, mi.is_private),
+            mi.offset,
+            mi.dev,
+            mi.inode,
+            pathname,
+        )
+    }
+}
+
+/// The page size of the running system.
+pub(crate) fn page_size() -> usize {
+    // SAFETY: `sysconf` has no preconditions.
+    unsafe { libc::sysconf(libc::_SC_PAGESIZE) as usize }
+}
+
+/// Parses `start-end perms offset major:minor inode [pathname]`.
+fn parse_line(line: &str) -> Option<MapInfo> {
+    let mut fields = line.splitn(6, ' ');
+    let (start, end) = fields.next()?.split_once('-')?;
+    let perms = fields.next()?.parse().ok()?;
+    let offset = fields.next()?;
+    let (major, minor) = fields.next()?.split_once(':')?;
+    let inode = fields.next()?;
+    // The path is padded to a fixed column and may itself contain spaces.
+    let pathname = fields
+        .next()
+        .map(|path| path.trim_start_matches(' '))
+        .filter(|path| !path.is_empty());
+
+    Some(MapInfo::new(
+        usize::from_str_radix(start, 16).ok()?,
+        usize::from_str_radix(end, 16).ok()?,
+        perms,
+        usize::from_str_radix(offset, 16).ok()?,
+        libc::makedev(
+            u32::from_str_radix(major, 16).ok()?,
+            u32::from_str_radix(minor, 16).ok()?,
+        ) as DeviceId,
+        inode.parse().ok()?,
+        pathname.map(str::to_owned),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_one(line: &str) -> MapInfo {
+        let mut maps = MapInfo::parse(line.as_bytes()).unwrap();
+        assert_eq!(maps.len(), 1);
+        maps.pop().unwrap()
+    }
+
+    #[test]
+    fn parses_file_mapping() {
+        let map = parse_one(
+            "7f3a1c000000-7f3a1c021000 r-xp 00002000 fd:01 1053911                    /usr/lib/libc.so.6\n",
+        );
+        assert_eq!(map.start, 0x7f3a1c000000);
+        assert_eq!(map.end, 0x7f3a1c021000);
+        assert_eq!(map.perms, Perms::READ | Perms::EXEC | Perms::PRIVATE);
+        assert_eq!(map.offset, 0x2000);
+        assert_eq!(map.dev, libc::makedev(0xfd, 0x01) as DeviceId);
+        assert_eq!(map.inode, 1053911);
+        assert_eq!(map.pathname.as_deref(), Some("/usr/lib/libc.so.6"));
+        assert!(!map.deleted);
+    }
+
+    #[test]
+    fn keeps_spaces_in_paths() {
+        let map = parse_one("1000-2000 r--s 00000000 08:02 42   /data/my app/lib  x.so");
+        assert_eq!(map.pathname.as_deref(), Some("/data/my app/lib  x.so"));
+        assert!(!map.perms.is_private());
+    }
+
+    #[test]
+    fn strips_deleted_suffix() {
+        let map = parse_one("1000-2000 r-xp 00000000 08:02 42 /tmp/libfoo.so (deleted)");
+        assert_eq!(map.pathname.as_deref(), Some("/tmp/libfoo.so"));
+        assert!(map.deleted);
+
+        // Without an inode the suffix is part of the name, e.g. of a named anonymous mapping.
+        let map = parse_one("1000-2000 rw-p 00000000 00:00 0 [anon:x (deleted)]");
+        assert_eq!(map.pathname.as_deref(), Some("[anon:x (deleted)]"));
+        assert!(!map.deleted);
+    }
+
+    #[test]
+    fn parses_anonymous_mappings() {
+        for line in [
+            "1000-2000 rw-p 00000000 00:00 0",
+            "1000-2000 rw-p 00000000 00:00 0                          ",
+        ] {
+            let map = parse_one(line);
+            assert_eq!(map.pathname, None);
+            assert_eq!(map.inode, 0);
+        }
+    }
+
+    #[test]
+    fn parses_bracketed_mappings() {
+        let map = parse_one("7ffd1e5f6000-7ffd1e617000 rw-p 00000000 00:00 0    [stack]");
+        assert_eq!(map.pathname.as_deref(), Some("[stack]"));
+        let map = parse_one("ffffffffff600000-ffffffffff601000 --xp 00000000 00:00 0 [vsyscall]");
+        assert_eq!(map.perms, Perms::EXEC | Perms::PRIVATE);
+        assert_eq!(map.pathname.as_deref(), Some("[vsyscall]"));
+    }
+
+    #[test]
+    fn parses_multiple_lines() {
+        let maps = MapInfo::parse(
+            &b"1000-2000 r--p 00000000 08:02 42 /a\n\n2000-3000 r-xp 00001000 08:02 42 /a\n"[..],
+        )
+        .unwrap();
+        assert_eq!(maps.len(), 2);
+        assert_eq!(maps[1].offset, 0x1000);
+    }
+
+    #[test]
+    fn rejects_malformed_lines() {
+        for line in [
+            "garbage",
+            "1000 r-xp 00000000 08:02 42 /a",
+            "1000-2000 r-xq 00000000 08:02 42 /a",
+            "1000-2000 rwx 00000000 08:02 42 /a",
+            "1000-zzzz r-xp 00000000 08:02 42 /a",
+            "1000-2000 r-xp 00000000 0802 42 /a",
+            "1000-2000 r-xp 00000000 08:02 inode /a",
+            "1000-2000 r-xp 00000000 08:02",
+        ] {
+            let err = MapInfo::parse(line.as_bytes()).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidData, "{line}");
+        }
+    }
+
+    #[test]
+    fn parses_perms() {
+        assert_eq!("rw-s".parse::<Perms>(), Ok(Perms::READ | Perms::WRITE));
+        assert_eq!("---p".parse::<Perms>(), Ok(Perms::PRIVATE));
+        assert!("rw-".parse::<Perms>().is_err());
+        assert_eq!(
+            Perms::from_prot(libc::PROT_READ as u8, true).to_string(),
+            "r--p"
+        );
+    }
+}
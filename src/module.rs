@@ -0,0 +1,493 @@
+//! Loaded modules, reconstructed from the memory map of the process.
+//!
+//! Consecutive mappings of the same file are grouped into a [`LoadedModule`], whose dynamic
+//! section is read on demand to list the GOT slots it imports through or to look up symbols.
+//! Modules are selected by address, by a predicate, or by a glob over their file name.
+
+use std::collections::{HashMap, HashSet};
+use std::ffi::c_void;
+use std::fs::File;
+use std::ops::Range;
+use std::os::unix::fs::{FileExt, MetadataExt};
+
+use crate::archive::EmbeddedLibrary;
+use crate::elf::{Elf, RelocationKind, ELF_MAGIC};
+use crate::{registry, DeviceId, Error, Inode, MapInfo, Result};
+
+/// A GOT slot through which a module refers to a symbol, as returned by
+/// [`LoadedModule::imports`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Import {
+    /// The name of the symbol.
+    pub symbol: String,
+    /// The type of the relocation that fills the slot.
+    pub kind: RelocationKind,
+    /// The address of the GOT slot.
+    pub slot: usize,
+    /// The address the slot currently points to.
+    pub target: usize,
+}
+
+/// A file mapped into memory, usually a loaded library, made up of consecutive [`MapInfo`]
+/// segments with the same device, inode and path.
+///
+/// # Examples
+/// ```no_run
+/// # extern "C" fn my_getpid() -> i32 { 2333 }
+/// let libc = lsplt_rs::LoadedModule::find_by_name("libc.so")?;
+/// lsplt_rs::register_hook(libc.dev(), libc.inode(), "getpid", my_getpid as *mut _, None)?;
+/// # Ok::<(), lsplt_rs::Error>(())
+/// ```
+#[derive(Debug, Clone)]
+pub struct LoadedModule {
+    segments: Vec<MapInfo>,
+}
+
+impl LoadedModule {
+    /// Groups the entries of /proc/self/maps into modules.
+    ///
+    /// Unlike [`from_maps`](LoadedModule::from_maps), the ELF headers that start a new module
+    /// are read from memory rather than from the mapped files.
+    pub fn scan_self() -> Vec<LoadedModule> {
+        Self::group(&MapInfo::scan_self(), |map| {
+            // SAFETY: the mapping is a private readable mapping of this process.
+            map.perms.is_private()
+                && map.perms.is_readable()
+                && unsafe { std::ptr::read_unaligned(map.start as *const [u8; 4]) } == ELF_MAGIC
+        })
+    }
+
+    /// Groups the entries of /proc/[pid]/maps into modules.
+    ///
+    /// # Arguments
+    /// * `pid` - The process id to scan. Use "self" for the current process.
+    pub fn scan(pid: &str) -> Vec<LoadedModule> {
+        Self::from_maps(&MapInfo::scan(pid))
+    }
+
+    /// Groups `maps` into modules.
+    ///
+    /// Consecutive file-backed entries with the same device, inode and path form one module.
+    /// Anonymous entries are skipped. A file offset that goes backwards, or an entry whose file
+    /// offset holds an ELF header, starts a new module, so libraries mapped directly from the
+    /// same archive are kept apart even if they are mapped next to each other.
+    ///
+    /// The ELF headers are read from the mapped files, so `maps` may belong to any process.
+    /// Files that cannot be opened never start a new module.
+    pub fn from_maps(maps: &[MapInfo]) -> Vec<LoadedModule> {
+        let mut files: HashMap<(DeviceId, Inode), Option<File>> = HashMap::new();
+        Self::group(maps, |map| {
+            let file = files.entry((map.dev, map.inode)).or_insert_with(|| {
+                map.pathname
+                    .as_deref()
+                    .filter(|_| !map.deleted)
+                    .and_then(|path| File::open(path).ok())
+                    .filter(|file| {
+                        file.metadata().is_ok_and(|metadata| {
+                            metadata.dev() as DeviceId == map.dev
+                                && metadata.ino() as Inode == map.inode
+                        })
+                    })
+            });
+            let mut magic = [0; 4];
+            file.as_ref().is_some_and(|file| {
+                file.read_exact_at(&mut magic, map.offset as u64).is_ok() && magic == ELF_MAGIC
+            })
+        })
+    }
+
+    /// Groups `maps` into modules, where `starts_image` tells whether an entry maps an ELF
+    /// header.
+    fn group(
+        maps: &[MapInfo],
+        mut starts_image: impl FnMut(&MapInfo) -> bool,
+    ) -> Vec<LoadedModule> {
+        let mut modules: Vec<LoadedModule> = Vec::new();
+        for map in maps.iter().filter(|map| map.inode != 0) {
+            match modules.last_mut() {
+                Some(module) if module.continues_with(map) && !starts_image(map) => {
+                    module.segments.push(map.clone())
+                }
+                _ => modules.push(LoadedModule {
+                    segments: vec![map.clone()],
+                }),
+            }
+        }
+        modules
+    }
+
+    /// Finds the module of the current process whose file name matches the glob `name`.
+    ///
+    /// `*` matches any run of characters and `?` a single one. The file name may continue with
+    /// a version suffix after the match, so `libc.so` also matches `libc.so.6`. If `name`
+    /// contains a `/`, it must match the whole path instead. This is the rule
+    /// [`register_hook_by_name`](crate::register_hook_by_name) uses.
+    ///
+    /// # Returns
+    /// The module, [`Error::LibraryNotFound`] if no module matches, or
+    /// [`Error::AmbiguousLibrary`] with the matching paths if several different libraries match.
+    /// A library that is mapped under several paths with the same device and inode counts once.
+    pub fn find_by_name(name: &str) -> Result<LoadedModule> {
+        Self::find_unique(name, |module| module.matches_glob(name))
+    }
+
+    /// Finds the module of the current process that contains `addr`.
+    pub fn find_by_address(addr: *const c_void) -> Option<LoadedModule> {
+        Self::scan_self()
+            .into_iter()
+            .find(|module| module.contains(addr as usize))
+    }
+
+    /// The device number of the mapped file.
+    pub fn dev(&self) -> DeviceId {
+        self.segments[0].dev
+    }
+
+    /// The inode of the mapped file.
+    pub fn inode(&self) -> Inode {
+        self.segments[0].inode
+    }
+
+    /// The path of the mapped file.
+    pub fn pathname(&self) -> &str {
+        self.segments[0].pathname.as_deref().unwrap_or_default()
+    }
+
+    /// The segments of the module, in address order.
+    pub fn segments(&self) -> &[MapInfo] {
+        &self.segments
+    }
+
+    /// The start address of the first segment, where the ELF header is mapped.
+    pub fn base(&self) -> usize {
+        self.segments[0].start
+    }
+
+    /// The address range from the start of the first segment to the end of the last one.
+    pub fn span(&self) -> Range<usize> {
+        self.base()..self.segments[self.segments.len() - 1].end
+    }
+
+    /// The address range of the executable segments, or `None` if no segment is executable.
+    pub fn executable_range(&self) -> Option<Range<usize>> {
+        let mut exec = self.segments.iter().filter(|map| map.perms.is_executable());
+        let first = exec.next()?;
+        let end = exec.next_back().unwrap_or(first).end;
+        Some(first.start..end)
+    }
+
+    /// The file offset of the first segment. This is non-zero for libraries mapped directly from
+    /// an archive, and is the `offset` to pass to
+    /// [`register_hook_with_offset`](crate::register_hook_with_offset).
+    pub fn offset(&self) -> usize {
+        self.segments[0].offset
+    }
+
+    /// Finds the library inside an archive this module was mapped from, see
+    /// [`MapInfo::embedded_library`].
+    pub fn embedded_library(&self) -> Result<Option<EmbeddedLibrary>> {
+        self.segments[0].embedded_library()
+    }
+
+    /// Whether `addr` lies within one of the segments.
+    pub fn contains(&self, addr: usize) -> bool {
+        self.segments
+            .iter()
+            .any(|map| map.start <= addr && addr < map.end)
+    }
+
+    /// Whether the module contains the code of this crate, i.e. is the library or executable
+    /// `lsplt-rs` is linked into.
+    pub fn is_self(&self) -> bool {
+        self.contains(LoadedModule::is_self as fn(&LoadedModule) -> bool as usize)
+    }
+
+    /// Whether the module is the dynamic linker, e.g. `ld-linux-x86-64.so.2` or Android's
+    /// `linker64`.
+    pub fn is_linker(&self) -> bool {
+        let file_name = self.pathname().rsplit('/').next().unwrap_or_default();
+        file_name.starts_with("ld-") || file_name == "linker" || file_name == "linker64"
+    }
+
+    /// Lists the GOT slots through which the module refers to symbols, which are the slots
+    /// [`register_hook`](crate::register_hook) can patch.
+    ///
+    /// The ELF image is located the same way hooks find it: by device, inode and
+    /// [`offset`](LoadedModule::offset), in a private readable segment. Its slots are read by
+    /// the same parser hooks use, so a symbol listed here is patched at exactly these slots.
+    ///
+    /// # Returns
+    /// One [`Import`] per GOT slot, in relocation table order, or [`Error::ElfNotFound`] if the
+    /// module is not an ELF image that can be hooked.
+    ///
+    /// # Examples
+    /// ```no_run
+    /// let libc = lsplt_rs::LoadedModule::find_by_name("libc.so")?;
+    /// for import in libc.imports()? {
+    ///     println!("{} {:?} {:#x} -> {:#x}", import.symbol, import.kind, import.slot, import.target);
+    /// }
+    /// # Ok::<(), lsplt_rs::Error>(())
+    /// ```
+    pub fn imports(&self) -> Result<Vec<Import>> {
+        let not_found = || Error::ElfNotFound {
+            dev: self.dev(),
+            inode: self.inode(),
+        };
+        let base = registry::find_base(&self.segments, self.dev(), self.inode(), self.offset())
+            .ok_or_else(not_found)?;
+        // SAFETY: `base` is a readable mapping of the module, as listed in /proc/self/maps.
+        let elf = unsafe { Elf::from_base(base.start) }.ok_or_else(not_found)?;
+        let mut imports = Vec::new();
+        let mut plt_symbols = HashSet::new();
+        elf.for_each_slot_relocation(|index, relocation| {
+            // Like `Elf::find_relocations`, only the first PLT slot of a symbol is patched.
+            let is_plt = relocation.kind == RelocationKind::JumpSlot;
+            if index == 0 || (is_plt && !plt_symbols.insert(index)) {
+                return false;
+            }
+            let Some(name) = elf.symbol_name(index) else {
+                return false;
+            };
+            imports.push(Import {
+                symbol: String::from_utf8_lossy(name).into_owned(),
+                kind: relocation.kind,
+                slot: relocation.address,
+                // SAFETY: the slot lies in the mapped image of the module.
+                target: unsafe { std::ptr::read_volatile(relocation.address as *const usize) },
+            });
+            false
+        });
+        Ok(imports)
+    }
+
+    /// `(offset, size)` to register hooks with, for libraries mapped from inside an archive.
+    pub(crate) fn offset_range(&self) -> Option<(usize, usize)> {
+        (self.offset() != 0).then(|| (self.offset(), self.span().len()))
+    }
+
+    /// Whether both modules are the same library, possibly mapped twice.
+    pub(crate) fn is_same_library(&self, other: &LoadedModule) -> bool {
+        self.dev() == other.dev()
+            && self.inode() == other.inode()
+            && self.offset() == other.offset()
+    }
+
+    /// Whether the module is an ELF image that references `symbol` through its GOT.
+    pub(crate) fn references_symbol(&self, symbol: &str) -> bool {
+        let first = &self.segments[0];
+        if !first.perms.is_readable() || !first.perms.is_private() {
+            return false;
+        }
+        // SAFETY: the first segment is readable and holds the ELF header if there is one.
+        unsafe { Elf::from_base(first.start) }
+            .is_some_and(|elf| !elf.find_plt_addr(symbol).is_empty())
+    }
+
+    /// Whether the file name of the module matches the glob `pattern`, possibly followed by a
+    /// version suffix starting with `.`, or the whole path if `pattern` contains a `/`. `*`
+    /// matches any run of characters and `?` a single one.
+    pub(crate) fn matches_glob(&self, pattern: &str) -> bool {
+        let path = self.pathname();
+        if pattern.contains('/') {
+            return glob_match(pattern.as_bytes(), path.as_bytes());
+        }
+        let file_name = path.rsplit('/').next().unwrap_or(path);
+        glob_match(pattern.as_bytes(), file_name.as_bytes())
+            || file_name
+                .match_indices('.')
+                .any(|(end, _)| glob_match(pattern.as_bytes(), &file_name.as_bytes()[..end]))
+    }
+
+    /// Finds the single module of the current process that satisfies `predicate`.
+    ///
+    /// Modules that share device, inode and offset are the same library mapped twice and count
+    /// once. `name` is only used for the error.
+    pub(crate) fn find_unique(
+        name: &str,
+        predicate: impl Fn(&LoadedModule) -> bool,
+    ) -> Result<LoadedModule> {
+        let mut matches: Vec<LoadedModule> = Vec::new();
+        for module in Self::scan_self().into_iter().filter(predicate) {
+            if !matches.iter().any(|found| found.is_same_library(&module)) {
+                matches.push(module);
+            }
+        }
+        match matches.len() {
+            0 => Err(Error::LibraryNotFound {
+                name: name.to_owned(),
+            }),
+            1 => Ok(matches.remove(0)),
+            _ => Err(Error::AmbiguousLibrary {
+                name: name.to_owned(),
+                paths: matches
+                    .iter()
+                    .map(|module| module.pathname().to_owned())
+                    .collect(),
+            }),
+        }
+    }
+
+    fn continues_with(&self, map: &MapInfo) -> bool {
+        let last = &self.segments[self.segments.len() - 1];
+        last.dev == map.dev
+            && last.inode == map.inode
+            && last.pathname == map.pathname
+            && last.offset <= map.offset
+    }
+}
+
+/// Matches `text` against `pattern`, where `*` matches any run of bytes and `?` a single one.
+///
+/// On a mismatch the last `*` takes one more byte and matching resumes right after it, so this
+/// needs no recursion and at most `pattern.len() * text.len()` steps.
+fn glob_match(pattern: &[u8], text: &[u8]) -> bool {
+    let (mut p, mut t) = (0, 0);
+    // The pattern position after the last `*`, and where in `text` its run ends.
+    let mut star = None;
+    while t < text.len() {
+        match pattern.get(p) {
+            Some(b'*') => {
+                p += 1;
+                star = Some((p, t));
+            }
+            Some(&c) if c == b'?' || c == text[t] => {
+                p += 1;
+                t += 1;
+            }
+            _ => {
+                let Some((after_star, run_end)) = star else {
+                    return false;
+                };
+                p = after_star;
+                t = run_end + 1;
+                star = Some((after_star, t));
+            }
+        }
+    }
+    pattern[p..].iter().all(|&c| c == b'*')
+}
+
+#[cfg(test)]
+mod tests {
+    use std::os::unix::fs::MetadataExt;
+
+    use super::*;
+    use crate::archive::tests::{build_zip, write_temp};
+    use crate::archive::METHOD_STORED;
+    use crate::maps::page_size;
+    use crate::Perms;
+
+    fn module(path: &str) -> LoadedModule {
+        LoadedModule {
+            segments: vec![MapInfo::new(
+                0x1000,
+                0x2000,
+                Perms::from_prot(libc::PROT_READ as u8, true),
+                0,
+                1,
+                1,
+                Some(path.to_owned()),
+            )],
+        }
+    }
+
+    #[test]
+    fn imports_the_slots_hooks_patch() {
+        let module = crate::testing::test_binary();
+        let imports = module.imports().unwrap();
+        // SAFETY: the test binary stays loaded.
+        let elf = unsafe { Elf::load(module.base()) }.unwrap();
+
+        let slots = |symbol: &str| -> Vec<usize> {
+            let slots = imports.iter().filter(|import| import.symbol == symbol);
+            slots.map(|import| import.slot).collect()
+        };
+        let patched = elf.find_relocations("getpid");
+        assert!(!patched.is_empty());
+        assert_eq!(
+            slots("getpid"),
+            patched
+                .iter()
+                .map(|relocation| relocation.address)
+                .collect::<Vec<_>>()
+        );
+        for import in &imports {
+            assert!(!elf.find_relocations(&import.symbol).is_empty());
+        }
+    }
+
+    #[test]
+    fn splits_adjacent_embedded_libraries() {
+        let page = page_size();
+        // Two libraries of two pages each, stored next to each other in one archive.
+        let mut library = b"\x7fELF".to_vec();
+        library.resize(2 * page, 0);
+        let zip = build_zip(&[
+            ("lib/arm64-v8a/liba.so", METHOD_STORED, &library),
+            ("lib/arm64-v8a/libb.so", METHOD_STORED, &library),
+        ]);
+        let path = write_temp("adjacent.apk", &zip);
+        let metadata = std::fs::metadata(&path).unwrap();
+        let (dev, inode) = (metadata.dev() as DeviceId, metadata.ino() as Inode);
+        let offsets: Vec<usize> = (0..zip.len())
+            .step_by(page)
+            .filter(|&offset| zip[offset..].starts_with(&ELF_MAGIC))
+            .collect();
+        assert_eq!(offsets.len(), 2);
+
+        // Both libraries are mapped where their file offsets put them relative to each other,
+        // so neither the addresses nor the offsets give the boundary away.
+        let base = 0x7000_0000;
+        let maps: Vec<MapInfo> = offsets
+            .iter()
+            .flat_map(|&offset| [offset, offset + page])
+            .map(|offset| {
+                MapInfo::new(
+                    base + offset,
+                    base + offset + page,
+                    Perms::from_prot(libc::PROT_READ as u8, true),
+                    offset,
+                    dev,
+                    inode,
+                    Some(path.to_str().unwrap().to_owned()),
+                )
+            })
+            .collect();
+        let modules = LoadedModule::from_maps(&maps);
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(modules.len(), 2);
+        for (module, &offset) in modules.iter().zip(&offsets) {
+            assert_eq!(module.offset(), offset);
+            assert_eq!(module.segments().len(), 2);
+        }
+    }
+
+    #[test]
+    fn matches_names_with_version_suffixes() {
+        let libc = module("/usr/lib/libc.so.6");
+        assert!(libc.matches_glob("libc.so"));
+        assert!(libc.matches_glob("libc.so*"));
+        assert!(libc.matches_glob("lib?.so.6"));
+        assert!(libc.matches_glob("/usr/lib/libc.so.6"));
+        assert!(!libc.matches_glob("libc.s"));
+        assert!(!libc.matches_glob("/usr/lib/libc.so"));
+        assert!(!module("/usr/lib/libc.sox").matches_glob("libc.so"));
+    }
+
+    #[test]
+    fn matches_globs() {
+        let matches = |pattern: &str, text: &str| glob_match(pattern.as_bytes(), text.as_bytes());
+        assert!(matches("libc.so*", "libc.so.6"));
+        assert!(matches("lib?.so", "libc.so"));
+        assert!(matches("*", ""));
+        assert!(matches("*foo*bar", "xfoofoobar"));
+        assert!(!matches("libc.so", "libc.so.6"));
+        assert!(!matches("lib?.so", "lib.so"));
+        assert!(!matches("*foo*bar", "foobaz"));
+        assert!(!matches("?", ""));
+        // Backtracking over many stars stays fast.
+        let text = "a".repeat(4096);
+        assert!(!matches(&("*a".repeat(64) + "b"), &text));
+    }
+}
@@ -0,0 +1,110 @@
+//! Dry runs of hooks, see [`plan_hook`](crate::plan_hook).
+
+use std::ops::Range;
+
+use crate::elf::RelocationKind;
+use crate::{DeviceId, Inode};
+
+/// A GOT slot a hook would patch.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PlannedSlot {
+    /// The address of the slot.
+    pub address: usize,
+    /// The `r_offset` of the relocation entry, relative to the load bias of the library.
+    pub r_offset: usize,
+    /// The type of the relocation entry.
+    pub kind: RelocationKind,
+    /// The value the slot currently holds, which the hook would overwrite.
+    pub current: usize,
+}
+
+/// What a hook would do if it was registered and committed, as returned by
+/// [`plan_hook`](crate::plan_hook).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HookPlan {
+    /// The device number of the library.
+    pub dev: DeviceId,
+    /// The inode of the library.
+    pub inode: Inode,
+    /// The file offset range of the library, for plans made with
+    /// [`plan_hook_with_offset`](crate::plan_hook_with_offset).
+    pub offset_range: Option<Range<usize>>,
+    /// The symbol to hook.
+    pub symbol: String,
+    /// The address the ELF header of the library is mapped at.
+    pub base: usize,
+    /// The slots that would be patched, in relocation table order.
+    pub slots: Vec<PlannedSlot>,
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::elf::Elf;
+    use crate::{testing, Error};
+
+    extern "C" fn fake_pid() -> i32 {
+        2333
+    }
+
+    #[test]
+    fn plans_without_patching() {
+        let _lock = testing::lock();
+        let module = testing::test_binary();
+        let pid = testing::real_pid();
+
+        let plan = crate::plan_hook(module.dev(), module.inode(), "getpid").unwrap();
+        assert_eq!((plan.dev, plan.inode), (module.dev(), module.inode()));
+        assert_eq!(plan.offset_range, None);
+        assert_eq!(plan.symbol, "getpid");
+        assert_eq!(plan.base, module.base());
+        // SAFETY: the test binary stays loaded.
+        let elf = unsafe { Elf::load(module.base()) }.unwrap();
+        let relocations = elf.find_relocations("getpid");
+        assert_eq!(plan.slots.len(), relocations.len());
+        for (slot, relocation) in plan.slots.iter().zip(&relocations) {
+            assert_eq!(slot.address, relocation.address);
+            assert_eq!(slot.r_offset, relocation.r_offset);
+            assert_eq!(slot.kind, relocation.kind);
+        }
+        assert!(!plan.slots.is_empty());
+        assert_eq!(testing::getpid(), pid);
+
+        // The current values are what a hook backs up.
+        let mut backup = std::ptr::null_mut();
+        crate::register_hook(
+            module.dev(),
+            module.inode(),
+            "getpid",
+            fake_pid as *mut _,
+            Some(&mut backup),
+        )
+        .unwrap();
+        crate::commit_hook().unwrap();
+        assert_eq!(plan.slots[0].current, backup as usize);
+        let hooked = crate::plan_hook(module.dev(), module.inode(), "getpid").unwrap();
+        assert!(hooked
+            .slots
+            .iter()
+            .all(|slot| slot.current == fake_pid as *const () as usize));
+        crate::register_hook(module.dev(), module.inode(), "getpid", backup, None).unwrap();
+        crate::commit_hook().unwrap();
+        assert_eq!(testing::getpid(), pid);
+    }
+
+    #[test]
+    fn reports_plan_errors() {
+        let module = testing::test_binary();
+        assert!(matches!(
+            crate::plan_hook(module.dev(), module.inode(), "lsplt_rs_test_missing"),
+            Err(Error::SymbolNotFound { .. })
+        ));
+        assert!(matches!(
+            crate::plan_hook(0, module.inode(), "getpid"),
+            Err(Error::InvalidArgument(_))
+        ));
+        assert!(matches!(
+            crate::plan_hook(module.dev(), u64::MAX as _, "getpid"),
+            Err(Error::NotMapped { .. })
+        ));
+    }
+}
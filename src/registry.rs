@@ -0,0 +1,638 @@
+//! Mirror of the registrations that have been handed to the backend.
+//!
+//! The backends only tell whether a commit succeeded as a whole, so every registration is also
+//! tracked here. Before committing, the GOT slots each registration should patch are resolved
+//! from the loaded ELF image; afterwards the slots are read back to see which ones were patched.
+
+use std::collections::HashMap;
+use std::ffi::{c_void, CString};
+use std::fs::File;
+use std::os::unix::fs::{FileExt, MetadataExt};
+use std::sync::atomic::{AtomicPtr, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use crate::elf::{Elf, SlotRelocation, ELF_MAGIC};
+use crate::plan::{HookPlan, PlannedSlot};
+use crate::report::{CommitReport, HookOutcome, HookReport, InstalledHook};
+use crate::{backend, maps, DeviceId, Error, Inode, MapInfo, Result};
+
+/// A registration that has been handed to the backend but not committed yet.
+#[derive(Debug, Clone)]
+pub(crate) struct Registration {
+    pub(crate) dev: DeviceId,
+    pub(crate) inode: Inode,
+    /// `(offset, size)` for hooks registered with an offset range.
+    pub(crate) offset_range: Option<(usize, usize)>,
+    pub(crate) symbol: String,
+    pub(crate) callback: usize,
+}
+
+impl Registration {
+    /// Checks the arguments, without registering anything. The backends rely on this check:
+    /// the pure-Rust backend does not repeat it.
+    pub(crate) fn validate(&self) -> Result<()> {
+        self.validate_target()?;
+        if self.callback == 0 {
+            return Err(Error::InvalidArgument("callback must not be null"));
+        }
+        Ok(())
+    }
+
+    /// Whether both registrations hook the same symbol of the same library.
+    pub(crate) fn same_target(&self, other: &Registration) -> bool {
+        self.dev == other.dev
+            && self.inode == other.inode
+            && self.offset_range == other.offset_range
+            && self.symbol == other.symbol
+    }
+
+    /// Checks the library and symbol arguments, ignoring the callback.
+    pub(crate) fn validate_target(&self) -> Result<()> {
+        if self.dev == 0 || self.inode == 0 {
+            return Err(Error::InvalidArgument("device and inode must be non-zero"));
+        }
+        if self.symbol.is_empty() {
+            return Err(Error::InvalidArgument("symbol must not be empty"));
+        }
+        CString::new(self.symbol.as_str())?;
+        self.validate_offset()
+    }
+
+    /// Checks the offset range the way the backends need it: `offset + size` must not
+    /// overflow, `offset` must be page aligned, and if the file is already mapped at `offset`,
+    /// an ELF image must start there.
+    ///
+    /// The magic is read from the mapped file rather than from memory, since the mapping may
+    /// have been replaced by a hook.
+    pub(crate) fn validate_offset(&self) -> Result<()> {
+        let Some((offset, size)) = self.offset_range else {
+            return Ok(());
+        };
+        offset
+            .checked_add(size)
+            .ok_or(Error::InvalidOffset { offset, size })?;
+        let page_size = maps::page_size();
+        if offset % page_size != 0 {
+            return Err(Error::UnalignedOffset { offset, page_size });
+        }
+        let maps = MapInfo::scan_self();
+        let Some(map) = maps.iter().find(|map| {
+            map.dev == self.dev
+                && map.inode == self.inode
+                && map.perms.is_readable()
+                && map.offset <= offset
+                && offset - map.offset < map.end - map.start
+        }) else {
+            return Ok(());
+        };
+        let Some(file) = map
+            .pathname
+            .as_deref()
+            .filter(|_| !map.deleted)
+            .and_then(|path| File::open(path).ok())
+            .filter(|file| {
+                file.metadata().is_ok_and(|metadata| {
+                    metadata.dev() as DeviceId == self.dev && metadata.ino() as Inode == self.inode
+                })
+            })
+        else {
+            return Ok(());
+        };
+        let mut magic = [0; 4];
+        if file.read_exact_at(&mut magic, offset as u64).is_err() || magic != ELF_MAGIC {
+            return Err(Error::ElfNotFound {
+                dev: self.dev,
+                inode: self.inode,
+            });
+        }
+        Ok(())
+    }
+}
+
+static PENDING: Mutex<Vec<Registration>> = Mutex::new(Vec::new());
+
+/// Locks the list of pending registrations.
+///
+/// Registering with the backend and committing happen while holding this lock so that the list
+/// always mirrors the backend.
+pub(crate) fn pending() -> MutexGuard<'static, Vec<Registration>> {
+    PENDING.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Validates `registration`, hands it to the backend and records it in `pending`.
+pub(crate) fn register(
+    pending: &mut Vec<Registration>,
+    registration: Registration,
+    backup: *mut *mut c_void,
+) -> Result<()> {
+    registration.validate()?;
+    hand_over(pending, registration, backup)
+}
+
+/// Registers `registration`, which puts back the original function of a hook this crate
+/// applied, without validating its target again.
+pub(crate) fn restore(pending: &mut Vec<Registration>, registration: Registration) -> Result<()> {
+    hand_over(pending, registration, std::ptr::null_mut())
+}
+
+fn hand_over(
+    pending: &mut Vec<Registration>,
+    registration: Registration,
+    backup: *mut *mut c_void,
+) -> Result<()> {
+    let callback = registration.callback as *mut c_void;
+    match registration.offset_range {
+        Some((offset, size)) => backend::register_hook_with_offset(
+            registration.dev,
+            registration.inode,
+            offset,
+            size,
+            &registration.symbol,
+            callback,
+            backup,
+        )?,
+        None => backend::register_hook(
+            registration.dev,
+            registration.inode,
+            &registration.symbol,
+            callback,
+            backup,
+        )?,
+    }
+    pending.push(registration);
+    Ok(())
+}
+
+/// Finds the mapping of the library identified by `dev`, `inode` and `offset` that holds its ELF
+/// header, the way the backends do.
+pub(crate) fn find_base(
+    maps: &[MapInfo],
+    dev: DeviceId,
+    inode: Inode,
+    offset: usize,
+) -> Option<&MapInfo> {
+    maps.iter().find(|map| {
+        map.dev == dev
+            && map.inode == inode
+            && map.offset == offset
+            && map.perms.is_private()
+            && map.perms.is_readable()
+    })
+}
+
+/// Resolves the GOT slots `registration` is going to patch.
+pub(crate) fn resolve_slots(
+    maps: &[MapInfo],
+    registration: &Registration,
+) -> std::result::Result<Vec<usize>, HookOutcome> {
+    let (_, relocations) = resolve_relocations(maps, registration)?;
+    Ok(relocations
+        .iter()
+        .map(|relocation| relocation.address)
+        .collect())
+}
+
+/// Resolves the base address of the library and the relocations of the GOT slots
+/// `registration` is going to patch.
+pub(crate) fn resolve_relocations(
+    maps: &[MapInfo],
+    registration: &Registration,
+) -> std::result::Result<(usize, Vec<SlotRelocation>), HookOutcome> {
+    let offset = registration.offset_range.map_or(0, |(offset, _)| offset);
+    let base = find_base(maps, registration.dev, registration.inode, offset)
+        .ok_or(HookOutcome::NotMapped)?;
+    // SAFETY: `base` is a readable mapping of a file the dynamic linker mapped for us.
+    let elf = unsafe { Elf::from_base(base.start) }.ok_or(HookOutcome::ElfNotFound)?;
+    let relocations = elf.find_relocations(&registration.symbol);
+    if relocations.is_empty() {
+        return Err(HookOutcome::SymbolNotFound);
+    }
+    Ok((base.start, relocations))
+}
+
+/// The error for a registration whose GOT slots could not be resolved.
+pub(crate) fn resolve_error(registration: &Registration, outcome: HookOutcome) -> Error {
+    match outcome {
+        HookOutcome::NotMapped => Error::NotMapped {
+            dev: registration.dev,
+            inode: registration.inode,
+        },
+        HookOutcome::ElfNotFound => Error::ElfNotFound {
+            dev: registration.dev,
+            inode: registration.inode,
+        },
+        _ => Error::SymbolNotFound {
+            symbol: registration.symbol.clone(),
+        },
+    }
+}
+
+/// Resolves what committing `registration` would patch, without writing anything.
+pub(crate) fn plan(registration: &Registration) -> Result<HookPlan> {
+    registration.validate_target()?;
+    let (base, relocations) = resolve_relocations(&MapInfo::scan_self(), registration)
+        .map_err(|outcome| resolve_error(registration, outcome))?;
+    Ok(HookPlan {
+        dev: registration.dev,
+        inode: registration.inode,
+        offset_range: registration
+            .offset_range
+            .map(|(offset, size)| offset..offset.saturating_add(size)),
+        symbol: registration.symbol.clone(),
+        base,
+        slots: relocations
+            .into_iter()
+            .map(|relocation| PlannedSlot {
+                address: relocation.address,
+                r_offset: relocation.r_offset,
+                kind: relocation.kind,
+                // SAFETY: the slot lies in the mapped image of the library.
+                current: unsafe { std::ptr::read_volatile(relocation.address as *const usize) },
+            })
+            .collect(),
+    })
+}
+
+/// Commits everything in `pending` and reports the outcome of every registration.
+///
+/// Registrations of libraries that are not mapped stay in `pending`, just like they stay queued
+/// in the backend.
+pub(crate) fn commit(pending: &mut Vec<Registration>) -> CommitReport {
+    let maps = MapInfo::scan_self();
+    let resolved: Vec<_> = pending
+        .iter()
+        .map(|registration| resolve_slots(&maps, registration))
+        .collect();
+
+    // The values of the slots before the commit, to record the original functions.
+    let before: Vec<Vec<usize>> = resolved
+        .iter()
+        .map(|slots| match slots {
+            Ok(slots) => slots
+                .iter()
+                // SAFETY: the slots lie in the mapped image of the library.
+                .map(|&slot| unsafe { std::ptr::read_volatile(slot as *const usize) })
+                .collect(),
+            Err(_) => Vec::new(),
+        })
+        .collect();
+
+    // Registrations are applied in order, so a slot hooked twice ends up with the callback of
+    // the last registration.
+    let mut expected = HashMap::new();
+    for (registration, slots) in pending.iter().zip(&resolved) {
+        for &slot in slots.iter().flatten() {
+            expected.insert(slot, registration.callback);
+        }
+    }
+
+    let error = backend::commit_hook().err();
+
+    let mut installed = installed();
+    installed.retain(|hook| {
+        maps.iter()
+            .any(|map| map.dev == hook.dev && map.inode == hook.inode)
+    });
+
+    let mut hooks = Vec::with_capacity(pending.len());
+    let mut still_pending = Vec::new();
+    for ((registration, slots), before) in pending.drain(..).zip(resolved).zip(before) {
+        let outcome = match slots {
+            Ok(slots) => {
+                let patched = slots
+                    .iter()
+                    // SAFETY: the slots lie in the mapped image of the library.
+                    .filter(|&&slot| unsafe {
+                        std::ptr::read_volatile(slot as *const usize) == expected[&slot]
+                    })
+                    .count();
+                if patched == slots.len() {
+                    record_installed(&mut installed, &registration, slots, &before);
+                    HookOutcome::Patched { slots: patched }
+                } else {
+                    HookOutcome::Failed {
+                        patched,
+                        slots: slots.len(),
+                    }
+                }
+            }
+            Err(outcome) => outcome,
+        };
+        hooks.push(HookReport {
+            dev: registration.dev,
+            inode: registration.inode,
+            offset_range: registration
+                .offset_range
+                .map(|(offset, size)| offset..offset.saturating_add(size)),
+            symbol: registration.symbol.clone(),
+            outcome: outcome.clone(),
+        });
+        if outcome == HookOutcome::NotMapped {
+            still_pending.push(registration);
+        }
+    }
+    *pending = still_pending;
+    drop(installed);
+
+    let mut report = CommitReport { hooks, error };
+    let restores = take_deferred_restores(pending);
+    if !restores.is_empty() {
+        let first = pending.len();
+        let registered: Vec<_> = restores
+            .into_iter()
+            .map(|(registration, on_release)| (restore(pending, registration).is_ok(), on_release))
+            .collect();
+        let restored = commit(pending);
+        let mut outcomes = restored.hooks[first..].iter();
+        for (registered, on_release) in registered {
+            let undone = registered
+                && outcomes
+                    .next()
+                    .is_some_and(|hook| matches!(hook.outcome, HookOutcome::Patched { .. }));
+            if let Some(on_release) = on_release {
+                on_release(undone);
+            }
+        }
+        if report.error.is_none() {
+            report.error = restored.error;
+        }
+    }
+    report
+}
+
+/// Called once a hook whose handle was dropped can no longer be applied, with `true` if its
+/// callback is unreachable again, or `false` if restoring the original function failed.
+pub(crate) type OnRelease = Box<dyn FnOnce(bool) + Send>;
+
+/// A hook whose [`HookHandle`](crate::HookHandle) was dropped before the hook was committed.
+struct DeferredRestore {
+    /// The target of the hook. The callback is unused.
+    registration: Registration,
+    /// Where the backend writes the original function once it applies the hook.
+    backup: Arc<AtomicPtr<c_void>>,
+    /// Whether to undo the hook once it is applied, rather than only keeping `backup` alive.
+    restore: bool,
+    on_release: Option<OnRelease>,
+}
+
+static DEFERRED: Mutex<Vec<DeferredRestore>> = Mutex::new(Vec::new());
+
+fn deferred() -> MutexGuard<'static, Vec<DeferredRestore>> {
+    DEFERRED.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Arranges for the hook `registration`, which has not been applied with `backup` as its backup
+/// slot, to be undone by the commit that applies it.
+///
+/// If the hook is no longer queued, it can never be applied and `on_release` is called right
+/// away. Takes `pending` so that no commit can write `backup` in between.
+pub(crate) fn defer_restore(
+    pending: &[Registration],
+    registration: Registration,
+    backup: Arc<AtomicPtr<c_void>>,
+    on_release: Option<OnRelease>,
+) {
+    if !pending
+        .iter()
+        .any(|pending| pending.same_target(&registration))
+    {
+        if let Some(on_release) = on_release {
+            on_release(true);
+        }
+        return;
+    }
+    deferred().push(DeferredRestore {
+        registration,
+        backup,
+        restore: true,
+        on_release,
+    });
+}
+
+/// Keeps `backup` alive while the hook `registration` is still queued in the backend with it as
+/// its backup slot, without undoing the hook.
+///
+/// Takes `pending` so that no commit can write `backup` in between.
+pub(crate) fn release_backup(
+    pending: &[Registration],
+    registration: Registration,
+    backup: Arc<AtomicPtr<c_void>>,
+) {
+    if pending
+        .iter()
+        .any(|pending| pending.same_target(&registration))
+    {
+        deferred().push(DeferredRestore {
+            registration,
+            backup,
+            restore: false,
+            on_release: None,
+        });
+    }
+}
+
+/// Returns the registrations that undo the deferred hooks applied by the last commit, and
+/// frees the backup slots the backend no longer holds.
+fn take_deferred_restores(pending: &[Registration]) -> Vec<(Registration, Option<OnRelease>)> {
+    let mut restores = Vec::new();
+    let mut dropped = Vec::new();
+    let mut deferred = deferred();
+    for entry in std::mem::take(&mut *deferred) {
+        let original = entry.backup.load(Ordering::Acquire);
+        if !original.is_null() {
+            if entry.restore {
+                let registration = Registration {
+                    callback: original as usize,
+                    ..entry.registration
+                };
+                restores.push((registration, entry.on_release));
+            }
+        } else if pending
+            .iter()
+            .any(|registration| registration.same_target(&entry.registration))
+        {
+            // The backend may still write the backup while the registration is pending.
+            deferred.push(entry);
+        } else {
+            dropped.extend(entry.on_release);
+        }
+    }
+    drop(deferred);
+    for on_release in dropped {
+        on_release(true);
+    }
+    restores
+}
+
+static INSTALLED: Mutex<Vec<InstalledHook>> = Mutex::new(Vec::new());
+
+/// Locks the list of hooks that are currently in place.
+pub(crate) fn installed() -> MutexGuard<'static, Vec<InstalledHook>> {
+    INSTALLED.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Updates `installed` after `registration` patched `slots`, which held `before` until then.
+fn record_installed(
+    installed: &mut Vec<InstalledHook>,
+    registration: &Registration,
+    slots: Vec<usize>,
+    before: &[usize],
+) {
+    match installed.iter().position(|hook| hook.slots == slots) {
+        Some(index) if installed[index].originals.contains(&registration.callback) => {
+            installed.remove(index);
+        }
+        Some(index) => installed[index].callback = registration.callback,
+        None if before.iter().all(|&value| value == registration.callback) => {}
+        None => installed.push(InstalledHook {
+            dev: registration.dev,
+            inode: registration.inode,
+            offset_range: registration
+                .offset_range
+                .map(|(offset, size)| offset..offset.saturating_add(size)),
+            symbol: registration.symbol.clone(),
+            callback: registration.callback,
+            originals: before.to_vec(),
+            slots,
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+
+    extern "C" fn fake_pid() -> i32 {
+        2333
+    }
+
+    extern "C" fn other_pid() -> i32 {
+        4242
+    }
+
+    #[test]
+    fn tracks_installed_hooks() {
+        let _lock = testing::lock();
+        let module = testing::test_binary();
+        let pid = testing::real_pid();
+        let find = || {
+            crate::installed_hooks()
+                .into_iter()
+                .find(|hook| hook.inode == module.inode() && hook.symbol == "getpid")
+        };
+        let register = |callback: *mut c_void, backup: Option<&mut _>| {
+            crate::register_hook(module.dev(), module.inode(), "getpid", callback, backup).unwrap();
+            crate::commit_hook().unwrap();
+        };
+        assert!(find().is_none());
+        let plan = crate::plan_hook(module.dev(), module.inode(), "getpid").unwrap();
+
+        let mut backup = std::ptr::null_mut();
+        register(fake_pid as *mut _, Some(&mut backup));
+        let hook = find().unwrap();
+        assert_eq!(hook.callback, fake_pid as *const () as usize);
+        assert_eq!(hook.offset_range, None);
+        assert_eq!(
+            hook.slots,
+            plan.slots
+                .iter()
+                .map(|slot| slot.address)
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(
+            hook.originals,
+            plan.slots
+                .iter()
+                .map(|slot| slot.current)
+                .collect::<Vec<_>>()
+        );
+
+        // Hooking the symbol again only replaces the callback.
+        register(other_pid as *mut _, None);
+        assert_eq!(testing::getpid(), 4242);
+        let replaced = find().unwrap();
+        assert_eq!(replaced.callback, other_pid as *const () as usize);
+        assert_eq!(replaced.originals, hook.originals);
+
+        // Restoring the original function removes the hook.
+        register(backup, None);
+        assert_eq!(testing::getpid(), pid);
+        assert!(find().is_none());
+    }
+
+    #[test]
+    fn rejects_invalid_offsets() {
+        let module = testing::test_binary();
+        let page_size = maps::page_size();
+        let register = |offset: usize, size: usize| {
+            crate::register_hook_with_offset(
+                module.dev(),
+                module.inode(),
+                offset,
+                size,
+                "getpid",
+                fake_pid as *mut c_void,
+                None,
+            )
+        };
+
+        assert!(matches!(
+            register(page_size, usize::MAX),
+            Err(Error::InvalidOffset { offset, size: usize::MAX }) if offset == page_size
+        ));
+        assert!(matches!(
+            register(page_size + 8, 0x1000),
+            Err(Error::UnalignedOffset { offset, page_size: size })
+                if offset == page_size + 8 && size == page_size
+        ));
+        // The second page of the test binary is mapped, but no ELF image starts there.
+        assert!(module.segments()[0].end - module.segments()[0].start > page_size);
+        assert!(matches!(
+            register(page_size, 0x1000),
+            Err(Error::ElfNotFound { .. })
+        ));
+        let span = module.span().len();
+        assert!(
+            crate::plan_hook_with_offset(module.dev(), module.inode(), 0, span, "getpid").is_ok()
+        );
+    }
+
+    #[test]
+    fn hooks_versioned_symbols() {
+        let _lock = testing::lock();
+        let module = testing::test_binary();
+        let pid = testing::real_pid();
+        // SAFETY: the test binary stays loaded.
+        let section = unsafe { crate::elf::DynamicSection::from_base(module.base()) }.unwrap();
+        let version = section.symbol("getpid").unwrap().version.clone().unwrap();
+        let symbol = format!("getpid@{version}");
+
+        let plan = crate::plan_hook(module.dev(), module.inode(), &symbol).unwrap();
+        assert_eq!(
+            plan.slots,
+            crate::plan_hook(module.dev(), module.inode(), "getpid")
+                .unwrap()
+                .slots
+        );
+        assert!(matches!(
+            crate::plan_hook(module.dev(), module.inode(), "getpid@LSPLT_RS_TEST"),
+            Err(Error::SymbolNotFound { .. })
+        ));
+
+        let mut backup = std::ptr::null_mut();
+        crate::register_hook(
+            module.dev(),
+            module.inode(),
+            &symbol,
+            fake_pid as *mut c_void,
+            Some(&mut backup),
+        )
+        .unwrap();
+        crate::commit_hook().unwrap();
+        assert_eq!(testing::getpid(), 2333);
+        crate::register_hook(module.dev(), module.inode(), &symbol, backup, None).unwrap();
+        crate::commit_hook().unwrap();
+        assert_eq!(testing::getpid(), pid);
+    }
+}
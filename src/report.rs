@@ -0,0 +1,240 @@
+//! Per-registration results of [`commit_hook_with_report`](crate::commit_hook_with_report).
+
+use std::ops::Range;
+
+use crate::{DeviceId, Error, Inode, Result};
+
+/// The outcome of a single registration in a commit.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum HookOutcome {
+    /// Every GOT slot referring to the symbol now points to the callback, or to the callback of
+    /// a later registration for the same slot in the same commit.
+    Patched {
+        /// The number of GOT slots that were patched.
+        slots: usize,
+    },
+    /// The library is not mapped. The registration stays pending and is applied by a later
+    /// commit once the library is loaded.
+    NotMapped,
+    /// The library is mapped, but no valid ELF image was found at the expected offset.
+    ElfNotFound,
+    /// The library does not reference the symbol through its GOT.
+    SymbolNotFound,
+    /// Only some or none of the GOT slots were patched.
+    Failed {
+        /// The number of GOT slots that were patched.
+        patched: usize,
+        /// The number of GOT slots that refer to the symbol.
+        slots: usize,
+    },
+}
+
+/// The result of one registration in a [`CommitReport`].
+#[derive(Debug, Clone)]
+pub struct HookReport {
+    /// The device number of the library.
+    pub dev: DeviceId,
+    /// The inode of the library.
+    pub inode: Inode,
+    /// The file offset range of the library, for hooks registered with
+    /// [`register_hook_with_offset`](crate::register_hook_with_offset).
+    pub offset_range: Option<Range<usize>>,
+    /// The hooked symbol.
+    pub symbol: String,
+    /// What happened to this registration.
+    pub outcome: HookOutcome,
+}
+
+impl HookReport {
+    /// Whether this registration failed. Registrations of libraries that are not mapped yet are
+    /// not considered failed.
+    pub fn is_failure(&self) -> bool {
+        !matches!(
+            self.outcome,
+            HookOutcome::Patched { .. } | HookOutcome::NotMapped
+        )
+    }
+
+    pub(crate) fn to_error(&self) -> Option<Error> {
+        match self.outcome {
+            HookOutcome::Patched { .. } | HookOutcome::NotMapped => None,
+            HookOutcome::ElfNotFound => Some(Error::ElfNotFound {
+                dev: self.dev,
+                inode: self.inode,
+            }),
+            HookOutcome::SymbolNotFound => Some(Error::SymbolNotFound {
+                symbol: self.symbol.clone(),
+            }),
+            HookOutcome::Failed { patched, slots } => {
+                Some(Error::MemoryProtection(std::io::Error::other(format!(
+                    "only {patched} of {slots} GOT slots of `{}` were patched",
+                    self.symbol
+                ))))
+            }
+        }
+    }
+}
+
+/// The result of [`commit_hook_with_report`](crate::commit_hook_with_report).
+#[derive(Debug)]
+pub struct CommitReport {
+    /// One entry for every registration that was pending when the commit started.
+    pub hooks: Vec<HookReport>,
+    /// The error reported by the backend for the commit as a whole, if any.
+    pub error: Option<Error>,
+}
+
+impl CommitReport {
+    /// Whether the backend succeeded and no registration failed.
+    pub fn is_success(&self) -> bool {
+        self.error.is_none() && !self.hooks.iter().any(HookReport::is_failure)
+    }
+
+    /// The registrations that failed.
+    pub fn failures(&self) -> impl Iterator<Item = &HookReport> {
+        self.hooks.iter().filter(|hook| hook.is_failure())
+    }
+
+    /// Converts the report into a result that fails if the backend or any registration failed:
+    /// the backend error if there is one, otherwise the error of the first failed registration.
+    ///
+    /// This is stricter than [`commit_hook`](crate::commit_hook), which only fails if the
+    /// backend does.
+    pub fn into_result(self) -> Result<()> {
+        if let Some(err) = self.error {
+            return Err(err);
+        }
+        match self.hooks.iter().find_map(HookReport::to_error) {
+            Some(err) => Err(err),
+            None => Ok(()),
+        }
+    }
+}
+
+/// A hook that is currently in place, as returned by [`installed_hooks`](crate::installed_hooks).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InstalledHook {
+    /// The device number of the library.
+    pub dev: DeviceId,
+    /// The inode of the library.
+    pub inode: Inode,
+    /// The file offset range of the library, for hooks registered with
+    /// [`register_hook_with_offset`](crate::register_hook_with_offset).
+    pub offset_range: Option<Range<usize>>,
+    /// The hooked symbol.
+    pub symbol: String,
+    /// The address of the callback the GOT slots point to.
+    pub callback: usize,
+    /// The values the GOT slots held before they were first hooked, one per entry of
+    /// [`slots`](InstalledHook::slots). They can differ, e.g. when a PLT slot still pointed to
+    /// the lazy binding stub.
+    pub originals: Vec<usize>,
+    /// The addresses of the patched GOT slots.
+    pub slots: Vec<usize>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+
+    extern "C" fn fake_pid() -> i32 {
+        2333
+    }
+
+    fn hook(symbol: &str, outcome: HookOutcome) -> HookReport {
+        HookReport {
+            dev: 1,
+            inode: 2,
+            offset_range: None,
+            symbol: symbol.to_owned(),
+            outcome,
+        }
+    }
+
+    #[test]
+    fn converts_reports_into_results() {
+        let patched = hook("getpid", HookOutcome::Patched { slots: 1 });
+        let report = CommitReport {
+            hooks: vec![patched.clone(), hook("dlopen", HookOutcome::NotMapped)],
+            error: None,
+        };
+        assert!(report.is_success());
+        assert!(report.into_result().is_ok());
+
+        let report = CommitReport {
+            hooks: vec![
+                patched.clone(),
+                hook("open", HookOutcome::SymbolNotFound),
+                hook("close", HookOutcome::ElfNotFound),
+            ],
+            error: None,
+        };
+        assert!(!report.is_success());
+        assert_eq!(report.failures().count(), 2);
+        assert!(matches!(
+            report.into_result(),
+            Err(Error::SymbolNotFound { symbol }) if symbol == "open"
+        ));
+
+        let report = CommitReport {
+            hooks: vec![
+                hook(
+                    "read",
+                    HookOutcome::Failed {
+                        patched: 1,
+                        slots: 2,
+                    },
+                ),
+                patched,
+            ],
+            error: None,
+        };
+        assert!(matches!(
+            report.into_result(),
+            Err(Error::MemoryProtection(_))
+        ));
+
+        // The backend error comes first.
+        let report = CommitReport {
+            hooks: vec![hook("open", HookOutcome::SymbolNotFound)],
+            error: Some(Error::InvalidArgument("backend")),
+        };
+        assert!(matches!(
+            report.into_result(),
+            Err(Error::InvalidArgument("backend"))
+        ));
+    }
+
+    #[test]
+    fn reports_every_registration() {
+        let _lock = testing::lock();
+        let module = testing::test_binary();
+        let pid = testing::real_pid();
+
+        let register = |symbol: &str, callback: *mut std::ffi::c_void, backup: Option<&mut _>| {
+            crate::register_hook(module.dev(), module.inode(), symbol, callback, backup).unwrap()
+        };
+        let mut backup = std::ptr::null_mut();
+        register("getpid", fake_pid as *mut _, Some(&mut backup));
+        register("lsplt_rs_test_missing", fake_pid as *mut _, None);
+        let report = crate::commit_hook_with_report();
+        assert_eq!(testing::getpid(), 2333);
+
+        let outcomes: Vec<_> = report.hooks.iter().map(|hook| &hook.outcome).collect();
+        assert!(matches!(
+            outcomes[..],
+            [HookOutcome::Patched { .. }, HookOutcome::SymbolNotFound]
+        ));
+        assert!(report.error.is_none());
+        assert!(matches!(
+            report.into_result(),
+            Err(Error::SymbolNotFound { symbol }) if symbol == "lsplt_rs_test_missing"
+        ));
+
+        register("getpid", backup, None);
+        crate::commit_hook().unwrap();
+        assert_eq!(testing::getpid(), pid);
+    }
+}
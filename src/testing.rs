@@ -0,0 +1,45 @@
+//! Helpers for the tests that hook the imports of the test binary.
+//!
+//! Commits are global, so these tests run one at a time. They hook functions such as `getpid`
+//! and `getuid` that the test harness itself does not call, and read the real values through `syscall`.
+
+use std::ffi::c_void;
+use std::sync::{Mutex, MutexGuard};
+
+use crate::LoadedModule;
+
+static LOCK: Mutex<()> = Mutex::new(());
+
+/// Serializes the tests that register hooks or commit.
+pub(crate) fn lock() -> MutexGuard<'static, ()> {
+    LOCK.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// The test binary, which imports the functions the tests call from libc.
+pub(crate) fn test_binary() -> LoadedModule {
+    LoadedModule::find_by_address(test_binary as *const c_void).expect("test binary not mapped")
+}
+
+/// The process id, read without going through the GOT.
+pub(crate) fn real_pid() -> i32 {
+    // SAFETY: `getpid` takes no arguments and cannot fail.
+    unsafe { libc::syscall(libc::SYS_getpid) as i32 }
+}
+
+/// Calls `getpid` through the GOT of the test binary.
+pub(crate) fn getpid() -> i32 {
+    // SAFETY: `getpid` cannot fail.
+    unsafe { libc::getpid() }
+}
+
+/// The real user id, read without going through the GOT.
+pub(crate) fn real_uid() -> u32 {
+    // SAFETY: `getuid` takes no arguments and cannot fail.
+    unsafe { libc::syscall(libc::SYS_getuid) as u32 }
+}
+
+/// Calls `getuid` through the GOT of the test binary.
+pub(crate) fn getuid() -> u32 {
+    // SAFETY: `getuid` cannot fail.
+    unsafe { libc::getuid() }
+}
@@ -0,0 +1,340 @@
+//! All-or-nothing batches of hooks.
+//!
+//! A [`HookTransaction`] only collects hooks until it is committed. Committing checks that
+//! every hook resolves to a GOT slot, registers and commits them together, and puts back the
+//! originals of the applied ones in reverse order if any hook of the batch was not applied.
+
+use std::ffi::c_void;
+use std::sync::atomic::{AtomicPtr, Ordering};
+use std::sync::Arc;
+
+use crate::registry::{self, Registration};
+use crate::report::{CommitReport, HookOutcome};
+use crate::{DeviceId, Error, HookTarget, Inode, MapInfo, Result};
+
+/// A batch of hooks that are committed all together or not at all.
+///
+/// Unlike [`register_hook`](crate::register_hook), adding a hook to a transaction does not touch
+/// the backend. Everything happens in [`commit`](HookTransaction::commit): the hooks are
+/// checked, registered and committed, and if any of them fails, the GOT slots that were already
+/// patched are restored to their original function pointers. Dropping a transaction without
+/// committing it has no effect.
+///
+/// # Examples
+/// ```no_run
+/// # let (dev, inode) = (0, 0);
+/// # extern "C" fn my_open() {}
+/// # extern "C" fn my_close() {}
+/// let mut open_backup = std::ptr::null_mut();
+/// let mut close_backup = std::ptr::null_mut();
+/// lsplt_rs::HookTransaction::new()
+///     .register_hook(dev, inode, "open", my_open as *mut _, Some(&mut open_backup))
+///     .register_hook(dev, inode, "close", my_close as *mut _, Some(&mut close_backup))
+///     .commit()?;
+/// # Ok::<(), lsplt_rs::Error>(())
+/// ```
+#[derive(Debug, Default)]
+pub struct HookTransaction<'a> {
+    hooks: Vec<(Registration, Option<&'a mut *mut c_void>)>,
+}
+
+impl<'a> HookTransaction<'a> {
+    /// Creates an empty transaction.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a hook to `symbol` in the library identified by `dev` and `inode`.
+    ///
+    /// The arguments are the same as for [`register_hook`](crate::register_hook). They are
+    /// checked when the transaction is committed, and `backup` is only written if the whole
+    /// transaction succeeds.
+    pub fn register_hook(
+        mut self,
+        dev: DeviceId,
+        inode: Inode,
+        symbol: &str,
+        callback: impl HookTarget,
+        backup: Option<&'a mut *mut c_void>,
+    ) -> Self {
+        self.hooks.push((
+            Registration {
+                dev,
+                inode,
+                offset_range: None,
+                symbol: symbol.to_owned(),
+                callback: callback.into_callback() as usize,
+            },
+            backup,
+        ));
+        self
+    }
+
+    /// Adds a hook to `symbol` in the library at `offset` of the file identified by `dev` and
+    /// `inode`.
+    ///
+    /// The arguments are the same as for
+    /// [`register_hook_with_offset`](crate::register_hook_with_offset). They are checked when the
+    /// transaction is committed, and `backup` is only written if the whole transaction succeeds.
+    #[allow(clippy::too_many_arguments)]
+    pub fn register_hook_with_offset(
+        mut self,
+        dev: DeviceId,
+        inode: Inode,
+        offset: usize,
+        size: usize,
+        symbol: &str,
+        callback: impl HookTarget,
+        backup: Option<&'a mut *mut c_void>,
+    ) -> Self {
+        self.hooks.push((
+            Registration {
+                dev,
+                inode,
+                offset_range: Some((offset, size)),
+                symbol: symbol.to_owned(),
+                callback: callback.into_callback() as usize,
+            },
+            backup,
+        ));
+        self
+    }
+
+    /// The number of hooks in this transaction.
+    pub fn len(&self) -> usize {
+        self.hooks.len()
+    }
+
+    /// Whether this transaction has no hooks.
+    pub fn is_empty(&self) -> bool {
+        self.hooks.is_empty()
+    }
+
+    /// Registers and commits every hook of this transaction.
+    ///
+    /// # Returns
+    /// The [`CommitReport`] of the commit if every hook of this transaction was applied, or the
+    /// first [`Error`] otherwise.
+    ///
+    /// # Notes
+    /// - This function is thread-safe. No other registration or commit can interleave with it.
+    /// - Before anything is registered, the arguments are checked and the GOT slots of every
+    ///   hook are resolved. If a library is not mapped, or does not reference the symbol, the
+    ///   transaction fails without side effects.
+    /// - If the commit fails for any hook, the hooks of this transaction that were applied are
+    ///   restored to their original function pointers and committed again. Hooks whose library
+    ///   was unloaded in the meantime stay queued in the backend, like with
+    ///   [`register_hook`](crate::register_hook), and are undone by the commit that applies them.
+    /// - Registrations that were pending before the transaction are committed along with it and
+    ///   are included in the report, but are never rolled back. If one of them fails, the
+    ///   transaction still succeeds and the failure, as well as the backend error, is left in
+    ///   the report.
+    pub fn commit(self) -> Result<CommitReport> {
+        let mut pending = registry::pending();
+
+        let maps = MapInfo::scan_self();
+        for (registration, _) in &self.hooks {
+            registration.validate()?;
+            if let Err(outcome) = registry::resolve_slots(&maps, registration) {
+                return Err(registry::resolve_error(registration, outcome));
+            }
+        }
+        self.apply(&mut pending)
+    }
+
+    /// Registers and commits the hooks, which were checked, and rolls them back if any of them
+    /// was not applied.
+    fn apply(self, pending: &mut Vec<Registration>) -> Result<CommitReport> {
+        // The backend writes the originals here, and keeps doing so for hooks that stay queued.
+        let originals: Vec<_> = self
+            .hooks
+            .iter()
+            .map(|_| Arc::new(AtomicPtr::new(std::ptr::null_mut())))
+            .collect();
+        let first = pending.len();
+        let mut registered = 0;
+        let mut error = None;
+        for ((registration, _), original) in self.hooks.iter().zip(&originals) {
+            match registry::register(pending, registration.clone(), original.as_ptr()) {
+                Ok(()) => registered += 1,
+                Err(err) => {
+                    error = Some(err);
+                    break;
+                }
+            }
+        }
+
+        let mut report = registry::commit(pending);
+        let ours = &report.hooks[first..first + registered];
+        for (((registration, _), original), hook) in self.hooks.iter().zip(&originals).zip(ours) {
+            if hook.outcome == HookOutcome::NotMapped {
+                // Still queued in the backend, so undo it once a later commit applies it.
+                registry::defer_restore(pending, registration.clone(), original.clone(), None);
+            }
+        }
+        // The backend error may come from a registration outside this transaction, so it only
+        // counts if one of ours was not applied.
+        let error = error.or_else(|| {
+            let hook = ours
+                .iter()
+                .find(|hook| !matches!(hook.outcome, HookOutcome::Patched { .. }))?;
+            Some(report.error.take().unwrap_or_else(|| {
+                hook.to_error().unwrap_or(Error::NotMapped {
+                    dev: hook.dev,
+                    inode: hook.inode,
+                })
+            }))
+        });
+        let originals: Vec<_> = originals
+            .iter()
+            .map(|original| original.load(Ordering::Acquire))
+            .collect();
+
+        if let Some(err) = error {
+            // In reverse, so a slot hooked twice ends up with the original of the first hook.
+            let mut restore = false;
+            for ((registration, _), &original) in self.hooks.iter().zip(&originals).rev() {
+                if original.is_null() {
+                    continue;
+                }
+                let registration = Registration {
+                    callback: original as usize,
+                    ..registration.clone()
+                };
+                restore |= registry::restore(pending, registration).is_ok();
+            }
+            if restore {
+                registry::commit(pending);
+            }
+            return Err(err);
+        }
+
+        for ((_, backup), original) in self.hooks.into_iter().zip(originals) {
+            if let Some(backup) = backup {
+                *backup = original;
+            }
+        }
+        Ok(report)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ffi::CString;
+    use std::fs;
+
+    use super::*;
+    use crate::elf::Elf;
+    use crate::{testing, LoadedModule};
+
+    extern "C" fn fake_pid() -> i32 {
+        2333
+    }
+
+    extern "C" fn fake_abort() {
+        unsafe { libc::abort() }
+    }
+
+    fn find(path: &str) -> Option<LoadedModule> {
+        LoadedModule::scan_self()
+            .into_iter()
+            .find(|module| module.pathname() == path && module.offset() == 0)
+    }
+
+    #[test]
+    fn rolls_back_after_a_partial_failure() {
+        let _lock = testing::lock();
+        let module = testing::test_binary();
+        let pid = testing::real_pid();
+
+        let mut backup = std::ptr::null_mut();
+        let transaction = HookTransaction::new()
+            .register_hook(
+                module.dev(),
+                module.inode(),
+                "getpid",
+                fake_pid as *mut c_void,
+                Some(&mut backup),
+            )
+            .register_hook(
+                module.dev(),
+                module.inode(),
+                "",
+                fake_pid as *mut c_void,
+                None,
+            );
+        // `commit` rejects the second hook up front, so go straight to registering.
+        let result = transaction.apply(&mut registry::pending());
+        assert!(matches!(result, Err(Error::InvalidArgument(_))));
+        assert_eq!(testing::getpid(), pid);
+        assert!(backup.is_null());
+    }
+
+    #[test]
+    fn defers_restoring_hooks_of_unloaded_libraries() {
+        let _lock = testing::lock();
+        let module = testing::test_binary();
+        let pid = testing::real_pid();
+        let Some(libgcc) = LoadedModule::scan_self()
+            .into_iter()
+            .find(|module| module.pathname().contains("/libgcc_s.") && module.offset() == 0)
+        else {
+            return;
+        };
+
+        // A copy of libgcc_s, so that it is really unloaded by `dlclose`.
+        let dir = std::env::temp_dir().join(format!("lsplt-rs-transaction-{}", std::process::id()));
+        let path = dir.join("libgcc_s.so.1");
+        fs::create_dir_all(&dir).unwrap();
+        fs::copy(libgcc.pathname(), &path).unwrap();
+        let c_path = CString::new(path.to_str().unwrap()).unwrap();
+        let dlopen = || unsafe { libc::dlopen(c_path.as_ptr(), libc::RTLD_NOW) };
+
+        let handle = dlopen();
+        assert!(!handle.is_null());
+        let copy = find(path.to_str().unwrap()).unwrap();
+        assert_eq!(unsafe { libc::dlclose(handle) }, 0);
+        assert!(find(path.to_str().unwrap()).is_none());
+
+        let transaction = HookTransaction::new()
+            .register_hook(
+                module.dev(),
+                module.inode(),
+                "getpid",
+                fake_pid as *mut c_void,
+                None,
+            )
+            .register_hook(
+                copy.dev(),
+                copy.inode(),
+                "abort",
+                fake_abort as *mut c_void,
+                None,
+            );
+        // `commit` finds that the copy is not mapped up front, so go straight to registering.
+        let result = transaction.apply(&mut registry::pending());
+        assert!(matches!(result, Err(Error::NotMapped { .. })));
+        assert_eq!(testing::getpid(), pid);
+
+        // The hook of the copy stays queued, and is undone by the commit that applies it.
+        let handle = dlopen();
+        assert!(!handle.is_null());
+        let report = crate::commit_hook_with_report();
+        assert!(report.hooks.iter().any(|hook| hook.inode == copy.inode()
+            && matches!(hook.outcome, HookOutcome::Patched { .. })));
+        let copy = find(path.to_str().unwrap()).unwrap();
+        let slots = unsafe { Elf::from_base(copy.base()) }
+            .unwrap()
+            .find_plt_addr("abort");
+        assert!(!slots.is_empty());
+        for slot in slots {
+            let target = unsafe { std::ptr::read_volatile(slot as *const usize) };
+            assert_ne!(target, fake_abort as *const () as usize);
+        }
+        assert!(registry::pending().is_empty());
+
+        unsafe { libc::dlclose(handle) };
+        fs::remove_file(&path).unwrap();
+        fs::remove_dir(&dir).unwrap();
+    }
+}
@@ -0,0 +1,49 @@
+//! Hooks the imports of this test binary through the LSPlt C++ core built for the host.
+#![cfg(all(feature = "cpp", not(feature = "pure-rust")))]
+
+use std::ffi::c_void;
+
+use lsplt_rs::LoadedModule;
+
+extern "C" fn fake_pid() -> i32 {
+    2333
+}
+
+/// The process id, read without going through the GOT.
+fn real_pid() -> i32 {
+    // SAFETY: `getpid` takes no arguments and cannot fail.
+    unsafe { libc::syscall(libc::SYS_getpid) as i32 }
+}
+
+/// Calls `getpid` through the GOT of this test binary.
+fn getpid() -> i32 {
+    // SAFETY: `getpid` cannot fail.
+    unsafe { libc::getpid() }
+}
+
+#[test]
+fn hooks_and_restores_through_the_cpp_core() {
+    let module = LoadedModule::find_by_address(real_pid as *const c_void).unwrap();
+    let pid = real_pid();
+
+    let mut backup = std::ptr::null_mut();
+    lsplt_rs::register_hook(
+        module.dev(),
+        module.inode(),
+        "getpid",
+        fake_pid as *mut c_void,
+        Some(&mut backup),
+    )
+    .unwrap();
+    lsplt_rs::commit_hook().unwrap();
+    assert_eq!(getpid(), 2333);
+    assert!(!backup.is_null());
+    // SAFETY: the backup is the original `getpid`.
+    let original: extern "C" fn() -> i32 = unsafe { std::mem::transmute(backup) };
+    assert_eq!(original(), pid);
+
+    lsplt_rs::register_hook(module.dev(), module.inode(), "getpid", backup, None).unwrap();
+    lsplt_rs::commit_hook().unwrap();
+    assert_eq!(getpid(), pid);
+    lsplt_rs::invalidate_backup().unwrap();
+}